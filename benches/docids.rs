@@ -0,0 +1,77 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use oqt::docids::{Backend, DocIds};
+use oqt::{Context, DocId, DocIndex, PostingsList, create_query_tree, traverse_query_tree};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use sdset::SetBuf;
+
+const WORDS: &[&str] = &["hello", "help", "world", "worlds", "word", "big", "bag", "the", "new", "york"];
+
+fn random_docids<R: Rng>(rng: &mut R, len: usize) -> SetBuf<DocId> {
+    let mut docids = BTreeSet::new();
+    while docids.len() != len {
+        docids.insert(rng.gen_range(0, 1_000_000));
+    }
+    SetBuf::new_unchecked(docids.into_iter().collect())
+}
+
+fn random_context<R: Rng>(rng: &mut R, len: usize) -> Context {
+    let postings: HashMap<_, _> = WORDS.iter().map(|word| {
+        let len = rng.gen_range(len / 10, len);
+        let docids = random_docids(rng, len);
+        let matches = docids.iter().map(|&docid| DocIndex { docid, position: rng.gen_range(0, 20) });
+        let matches = SetBuf::new_unchecked(matches.collect());
+        (word.to_string(), PostingsList { docids, matches })
+    })
+    .collect();
+
+    Context::new(HashMap::new(), postings)
+}
+
+fn operations(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut group = c.benchmark_group("operations");
+
+    for &len in &[1_000, 100_000] {
+        let sets: Vec<_> = (0..5).map(|_| DocIds::Set(Cow::Owned(random_docids(&mut rng, len)))).collect();
+
+        for &backend in &[Backend::Sdset, Backend::Roaring] {
+            let docids: Vec<_> = sets.iter().cloned().map(|d| d.into_backend(backend)).collect();
+            let docids: Vec<_> = docids.iter().collect();
+
+            let id = BenchmarkId::new(format!("union/{:?}", backend), len);
+            group.bench_with_input(id, &docids, |b, docids| b.iter(|| DocIds::union(docids)));
+
+            let id = BenchmarkId::new(format!("intersection/{:?}", backend), len);
+            group.bench_with_input(id, &docids, |b, docids| b.iter(|| DocIds::intersection(docids)));
+        }
+    }
+
+    group.finish();
+}
+
+fn traverse(c: &mut Criterion) {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut context = random_context(&mut rng, 100_000);
+    let queries = ["hello world", "hel", "big bag -the", "new york worlds"];
+
+    let mut group = c.benchmark_group("traverse");
+
+    for &backend in &[Backend::Sdset, Backend::Roaring] {
+        if backend == Backend::Roaring { context.use_bitmaps() }
+
+        for query in &queries {
+            let (tree, _) = create_query_tree(&context, query);
+            let id = BenchmarkId::new(format!("{:?}", backend), query);
+            group.bench_with_input(id, &tree, |b, tree| b.iter(|| traverse_query_tree(&context, tree)));
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, operations, traverse);
+criterion_main!(benches);
@@ -0,0 +1,137 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use sdset::{Set, SetOperation};
+
+use crate::criterion::Criteria;
+use crate::{Derivation, DocId, DocIndex, Position, Query, QueryId, QueryKind};
+
+// A match of a document, the query it comes from is represented by the
+// range of words it covers in the query once synonyms have been mapped,
+// and by the range of the words of the original query it replaces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BareMatch {
+    pub query_index: usize,
+    pub query_len: usize,
+    pub position: Position,
+    pub typos: u8,
+    pub is_exact: bool,
+    pub original_index: usize,
+    pub original_len: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct RawDocument {
+    pub id: DocId,
+    // Sorted by query index, query length and position.
+    pub matches: Vec<BareMatch>,
+}
+
+pub fn raw_documents(
+    docids: &Set<DocId>,
+    queries: &HashMap<&Query, Vec<Derivation>>,
+    mapping: &HashMap<QueryId, Range<usize>>,
+) -> Vec<RawDocument>
+{
+    let mut matches: HashMap<DocId, Vec<BareMatch>> = HashMap::new();
+    let originals = original_ranges(mapping);
+
+    for (query, derivations) in queries {
+        // A phrase is mapped to the range of all of its words.
+        let (query_word, len) = match &query.kind {
+            QueryKind::Tolerant(word) | QueryKind::Exact(word) => (Some(word), 1),
+            QueryKind::Phrase(words) => (None, words.len()),
+        };
+
+        let first = mapping.get(&query.id);
+        let last = mapping.get(&(query.id + len - 1));
+        let range = match (first, last) {
+            (Some(first), Some(last)) => first.start..last.end,
+            _ => continue,
+        };
+
+        // The original words the mapped range overlaps.
+        let original_start = originals.iter().position(|r| r.end > range.start).unwrap_or(originals.len());
+        let original_end = originals.iter().position(|r| r.start >= range.end).unwrap_or(originals.len());
+
+        for Derivation { word, typos, matches: derived } in derivations {
+            // The words split from a query word are not what the user typed.
+            let is_exact = *typos == 0 && !query.split && query_word.map_or(true, |w| w == word);
+
+            let op = sdset::duo::IntersectionByKey::new(derived, docids, |m| m.docid, Clone::clone);
+            for DocIndex { docid, position } in op.into_set_buf().into_vec() {
+                let bare = BareMatch {
+                    query_index: range.start,
+                    query_len: range.len(),
+                    position,
+                    typos: *typos,
+                    is_exact,
+                    original_index: original_start,
+                    original_len: original_end - original_start,
+                };
+                matches.entry(docid).or_default().push(bare);
+            }
+        }
+    }
+
+    docids.iter().map(|id| {
+        let mut matches = matches.remove(id).unwrap_or_default();
+        matches.sort_unstable();
+        RawDocument { id: *id, matches }
+    })
+    .collect()
+}
+
+// The ranges the words of the original query are mapped to, the original words are given
+// the first ids and their ranges follow each other, the alternatives are given the next ids.
+fn original_ranges(mapping: &HashMap<QueryId, Range<usize>>) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    while let Some(range) = mapping.get(&ranges.len()) {
+        if range.start != ranges.last().map_or(0, |r| r.end) { break }
+        ranges.push(range.clone());
+    }
+    ranges
+}
+
+// Sorts the documents by applying the criteria one after the other
+// on the groups of documents the previous criteria considered equal.
+// Only the groups overlapping the requested range are sorted.
+pub fn bucket_sort(
+    mut documents: Vec<RawDocument>,
+    criteria: &Criteria,
+    range: Range<usize>,
+) -> Vec<RawDocument>
+{
+    let mut groups = vec![0..documents.len()];
+
+    for criterion in criteria.iter() {
+        let mut new_groups = Vec::with_capacity(groups.len());
+
+        for group in groups {
+            if group.end <= range.start || group.start >= range.end {
+                new_groups.push(group);
+                continue;
+            }
+
+            let documents = &mut documents[group.clone()];
+            documents.sort_by(|a, b| criterion.evaluate(a, b));
+
+            let mut start = group.start;
+            for i in 1..=documents.len() {
+                let is_equal = i != documents.len()
+                    && criterion.evaluate(&documents[i - 1], &documents[i]) == Ordering::Equal;
+                if !is_equal {
+                    new_groups.push(start..group.start + i);
+                    start = group.start + i;
+                }
+            }
+        }
+
+        groups = new_groups;
+    }
+
+    let end = range.end.min(documents.len());
+    let start = range.start.min(end);
+    documents.drain(start..end).collect()
+}
@@ -0,0 +1,300 @@
+use std::cmp::{self, Ordering, Reverse};
+
+use slice_group_by::GroupBy;
+
+use crate::bucket_sort::{BareMatch, RawDocument};
+use crate::proximity::document_proximity;
+use crate::{attribute_of, word_index_of};
+
+pub trait Criterion {
+    fn name(&self) -> &str;
+
+    // The best documents are the smallest ones.
+    fn evaluate(&self, lhs: &RawDocument, rhs: &RawDocument) -> Ordering;
+
+    // Whether the documents are ranked by their number of typos, as counted by `number_of_typos`.
+    fn is_typo(&self) -> bool { false }
+}
+
+pub struct Criteria<'a> {
+    inner: Vec<Box<dyn Criterion + 'a>>,
+}
+
+impl<'a> Criteria<'a> {
+    pub fn new() -> Criteria<'a> {
+        Criteria { inner: Vec::new() }
+    }
+
+    pub fn push<C: Criterion + 'a>(mut self, criterion: C) -> Criteria<'a> {
+        self.inner.push(Box::new(criterion));
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&dyn Criterion> {
+        self.inner.iter().map(AsRef::as_ref)
+    }
+}
+
+impl Default for Criteria<'_> {
+    fn default() -> Self {
+        Criteria::new()
+            .push(Typo)
+            .push(Words)
+            .push(Proximity)
+            .push(Attribute)
+            .push(Exactness)
+            .push(DocumentId)
+    }
+}
+
+// The matches of the different queries mapped to the same query words.
+fn query_groups(matches: &[BareMatch]) -> impl Iterator<Item=&[BareMatch]> {
+    matches.linear_group_by(|a, b| a.query_index == b.query_index)
+}
+
+// Prefers the documents with the fewest typos,
+// counting the best alternative of each query word.
+pub struct Typo;
+
+pub fn number_of_typos(document: &RawDocument) -> usize {
+    query_groups(&document.matches)
+        .map(|group| group.iter().map(|m| m.typos as usize).min().unwrap_or(0))
+        .sum()
+}
+
+impl Criterion for Typo {
+    fn name(&self) -> &str { "typo" }
+
+    fn evaluate(&self, lhs: &RawDocument, rhs: &RawDocument) -> Ordering {
+        number_of_typos(lhs).cmp(&number_of_typos(rhs))
+    }
+
+    fn is_typo(&self) -> bool { true }
+}
+
+// Prefers the documents matching the most words of the original query,
+// a word replaced by a synonym of many words still counts as one word.
+pub struct Words;
+
+fn number_of_words(document: &RawDocument) -> usize {
+    let mut count = 0;
+    let mut covered = 0;
+
+    // The matches are sorted by query index and so by original index, we
+    // count the original words that are not already covered by a previous match.
+    for m in &document.matches {
+        let end = m.original_index + m.original_len;
+        count += end.saturating_sub(cmp::max(m.original_index, covered));
+        covered = cmp::max(covered, end);
+    }
+
+    count
+}
+
+impl Criterion for Words {
+    fn name(&self) -> &str { "words" }
+
+    fn evaluate(&self, lhs: &RawDocument, rhs: &RawDocument) -> Ordering {
+        Reverse(number_of_words(lhs)).cmp(&Reverse(number_of_words(rhs)))
+    }
+}
+
+// Prefers the documents where the query words are the closest to each other.
+pub struct Proximity;
+
+impl Criterion for Proximity {
+    fn name(&self) -> &str { "proximity" }
+
+    fn evaluate(&self, lhs: &RawDocument, rhs: &RawDocument) -> Ordering {
+        document_proximity(&lhs.matches).cmp(&document_proximity(&rhs.matches))
+    }
+}
+
+// Prefers the documents where the query words appear in the first attributes,
+// then the ones where they appear the earliest in these attributes.
+pub struct Attribute;
+
+fn sum_of_attributes(document: &RawDocument) -> (usize, usize) {
+    query_groups(&document.matches)
+        .filter_map(|group| group.iter().map(|m| m.position).min())
+        .fold((0, 0), |(attributes, indexes), position| {
+            let attribute = attribute_of(position) as usize;
+            let word_index = word_index_of(position) as usize;
+            (attributes + attribute, indexes + word_index)
+        })
+}
+
+impl Criterion for Attribute {
+    fn name(&self) -> &str { "attribute" }
+
+    fn evaluate(&self, lhs: &RawDocument, rhs: &RawDocument) -> Ordering {
+        sum_of_attributes(lhs).cmp(&sum_of_attributes(rhs))
+    }
+}
+
+// Prefers the documents matching the most query words
+// exactly, without typos, prefix expansion or splitting.
+pub struct Exactness;
+
+fn number_of_exact_words(document: &RawDocument) -> usize {
+    query_groups(&document.matches).filter(|group| group.iter().any(|m| m.is_exact)).count()
+}
+
+impl Criterion for Exactness {
+    fn name(&self) -> &str { "exactness" }
+
+    fn evaluate(&self, lhs: &RawDocument, rhs: &RawDocument) -> Ordering {
+        Reverse(number_of_exact_words(lhs)).cmp(&Reverse(number_of_exact_words(rhs)))
+    }
+}
+
+// Breaks the ties with the document ids, it gives a deterministic order.
+pub struct DocumentId;
+
+impl Criterion for DocumentId {
+    fn name(&self) -> &str { "document id" }
+
+    fn evaluate(&self, lhs: &RawDocument, rhs: &RawDocument) -> Ordering {
+        lhs.id.cmp(&rhs.id)
+    }
+}
+
+// A user defined criterion, to break ties with external data for example.
+pub struct Custom<F> {
+    name: String,
+    compare: F,
+}
+
+impl<F> Custom<F>
+where F: Fn(&RawDocument, &RawDocument) -> Ordering,
+{
+    pub fn new(name: &str, compare: F) -> Custom<F> {
+        Custom { name: name.to_owned(), compare }
+    }
+}
+
+impl<F> Criterion for Custom<F>
+where F: Fn(&RawDocument, &RawDocument) -> Ordering,
+{
+    fn name(&self) -> &str { &self.name }
+
+    fn evaluate(&self, lhs: &RawDocument, rhs: &RawDocument) -> Ordering {
+        (self.compare)(lhs, rhs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use big_s::S;
+    use crate::bucket_sort::bucket_sort;
+    use crate::fixtures::titles;
+    use crate::top_k::top_k;
+    use crate::{DocId, Position, create_query_tree, encode_position};
+
+    fn bare(query_index: usize, position: Position, typos: u8) -> BareMatch {
+        let is_exact = typos == 0;
+        BareMatch { query_index, query_len: 1, position, typos, is_exact, original_index: query_index, original_len: 1 }
+    }
+
+    fn document(id: DocId, mut matches: Vec<BareMatch>) -> RawDocument {
+        matches.sort_unstable();
+        RawDocument { id, matches }
+    }
+
+    #[test]
+    fn typos_then_proximity() {
+        let documents = vec![
+            document(0, vec![bare(0, 0, 1), bare(1, 1, 0)]),
+            document(1, vec![bare(0, 0, 0), bare(1, 5, 0)]),
+            document(2, vec![bare(0, 3, 0), bare(1, 4, 0)]),
+            document(3, vec![bare(0, 3, 0), bare(0, 9, 1), bare(1, 2, 0)]),
+        ];
+
+        let criteria = Criteria::new().push(Typo).push(Proximity).push(DocumentId);
+        let documents = bucket_sort(documents, &criteria, 0..10);
+        let ids: Vec<_> = documents.iter().map(|d| d.id).collect();
+
+        assert_eq!(ids, vec![2, 3, 1, 0]);
+    }
+
+    #[test]
+    fn words_count_mapped_ranges() {
+        let nyc = BareMatch { query_len: 3, original_len: 3, ..bare(0, 0, 0) };
+        let new = bare(0, 4, 0);
+        let subway = bare(3, 1, 0);
+
+        assert_eq!(number_of_words(&document(0, vec![nyc, subway])), 4);
+        assert_eq!(number_of_words(&document(1, vec![new, subway])), 2);
+        assert_eq!(number_of_words(&document(2, vec![nyc, new])), 3);
+
+        // The query `hello world` where `hello` is mapped to the synonym `good morning`.
+        let good = BareMatch { original_len: 1, ..bare(0, 0, 0) };
+        let hello = BareMatch { query_len: 2, original_len: 1, ..bare(0, 0, 0) };
+        let world = BareMatch { original_index: 1, ..bare(2, 1, 0) };
+
+        assert_eq!(number_of_words(&document(0, vec![hello, world])), 2);
+        assert_eq!(number_of_words(&document(1, vec![good])), 1);
+        assert_eq!(number_of_words(&document(2, vec![hello])), number_of_words(&document(3, vec![world])));
+    }
+
+    #[test]
+    fn custom_tie_break() {
+        let documents = vec![
+            document(0, vec![bare(0, 0, 0)]),
+            document(1, vec![bare(0, 0, 0)]),
+            document(2, vec![bare(0, 0, 1)]),
+        ];
+
+        let criteria = Criteria::new()
+            .push(Typo)
+            .push(Custom::new("decreasing id", |a: &RawDocument, b: &RawDocument| b.id.cmp(&a.id)));
+
+        let documents = bucket_sort(documents, &criteria, 0..2);
+        let ids: Vec<_> = documents.iter().map(|d| d.id).collect();
+
+        assert_eq!(ids, vec![1, 0]);
+    }
+
+    #[test]
+    fn earlier_attributes() {
+        let documents = vec![
+            document(0, vec![bare(0, encode_position(1, 0), 0), bare(1, encode_position(1, 1), 0)]),
+            document(1, vec![bare(0, encode_position(0, 9), 0), bare(1, encode_position(2, 0), 0)]),
+            document(2, vec![bare(0, encode_position(0, 3), 0), bare(1, encode_position(2, 0), 0)]),
+            document(3, vec![bare(0, encode_position(0, 3), 0), bare(0, encode_position(3, 0), 0)]),
+        ];
+
+        let criteria = Criteria::new().push(Attribute).push(DocumentId);
+        let documents = bucket_sort(documents, &criteria, 0..10);
+        let ids: Vec<_> = documents.iter().map(|d| d.id).collect();
+
+        assert_eq!(ids, vec![3, 0, 2, 1]);
+    }
+
+    #[test]
+    fn words_of_the_original_query() {
+        let mut ctx = titles(&["hello world", "good morning world", "world"]);
+        ctx.synonyms.insert(vec![S("hello")], vec![vec![S("good"), S("morning")]]);
+
+        let criteria = Criteria::new().push(Words).push(DocumentId);
+        let (tree, mapping) = create_query_tree(&ctx, "hello world");
+        let documents = top_k(&ctx, &tree, &mapping, &criteria, 2).documents;
+        let words: Vec<_> = documents.iter().map(|d| (d.id, number_of_words(d))).collect();
+
+        // The synonym replaces a single word of the query.
+        assert_eq!(words, vec![(0, 2), (1, 2)]);
+    }
+
+    #[test]
+    fn split_words_not_exact() {
+        let ctx = titles(&["hello world", "helloworld"]);
+
+        let criteria = Criteria::new().push(Exactness).push(DocumentId);
+        let (tree, mapping) = create_query_tree(&ctx, "helloworld");
+        let documents = top_k(&ctx, &tree, &mapping, &criteria, 2).documents;
+        let ids: Vec<_> = documents.iter().map(|d| d.id).collect();
+
+        assert_eq!(ids, vec![1, 0]);
+    }
+}
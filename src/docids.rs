@@ -0,0 +1,187 @@
+use std::borrow::Cow;
+
+use roaring::{MultiOps, RoaringBitmap};
+use sdset::{Set, SetBuf, SetOperation};
+
+use crate::DocId;
+
+// The representation of the docids while a query tree is evaluated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    // The docids are sorted sets of integers.
+    Sdset,
+    // The docids are compressed bitmaps, they must have been
+    // computed for the postings lists with `Context::use_bitmaps`.
+    Roaring,
+}
+
+// The docids of an operation, borrowed from the
+// postings lists or computed while evaluating the tree.
+#[derive(Debug, Clone)]
+pub enum DocIds<'c> {
+    Set(Cow<'c, Set<DocId>>),
+    Bitmap(Cow<'c, RoaringBitmap>),
+}
+
+impl Default for DocIds<'_> {
+    fn default() -> Self {
+        DocIds::Set(Cow::default())
+    }
+}
+
+impl<'c> DocIds<'c> {
+    pub fn len(&self) -> usize {
+        match self {
+            DocIds::Set(set) => set.len(),
+            DocIds::Bitmap(bitmap) => bitmap.len() as usize,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        match self {
+            DocIds::Set(set) => set.is_empty(),
+            DocIds::Bitmap(bitmap) => bitmap.is_empty(),
+        }
+    }
+
+    // Returns the docids as a set, the bitmaps are decompressed.
+    pub fn as_set(&self) -> Cow<'_, Set<DocId>> {
+        match self {
+            DocIds::Set(set) => Cow::Borrowed(set.as_ref()),
+            DocIds::Bitmap(bitmap) => Cow::Owned(bitmap_to_set(bitmap)),
+        }
+    }
+
+    pub fn into_set(self) -> Cow<'c, Set<DocId>> {
+        match self {
+            DocIds::Set(set) => set,
+            DocIds::Bitmap(bitmap) => Cow::Owned(bitmap_to_set(&bitmap)),
+        }
+    }
+
+    // Returns the docids in the representation of the backend.
+    pub fn into_backend(self, backend: Backend) -> DocIds<'c> {
+        match (self, backend) {
+            (DocIds::Set(set), Backend::Roaring) => DocIds::Bitmap(Cow::Owned(set_to_bitmap(&set))),
+            (DocIds::Bitmap(bitmap), Backend::Sdset) => DocIds::Set(Cow::Owned(bitmap_to_set(&bitmap))),
+            (docids, _) => docids,
+        }
+    }
+
+    // The bitmaps are only used when all the docids are bitmaps,
+    // the sets are used otherwise.
+    pub fn intersection(docids: &[&DocIds<'_>]) -> DocIds<'c> {
+        match bitmaps(docids) {
+            Some(bitmaps) => DocIds::Bitmap(Cow::Owned(bitmaps.intersection())),
+            None => {
+                let sets: Vec<_> = docids.iter().map(|d| d.as_set()).collect();
+                let sets: Vec<_> = sets.iter().map(AsRef::as_ref).collect();
+                DocIds::Set(Cow::Owned(sdset::multi::Intersection::new(sets).into_set_buf()))
+            },
+        }
+    }
+
+    pub fn union(docids: &[&DocIds<'_>]) -> DocIds<'c> {
+        match bitmaps(docids) {
+            Some(bitmaps) => DocIds::Bitmap(Cow::Owned(bitmaps.union())),
+            None => {
+                let mut ids = Vec::new();
+                for docids in docids {
+                    ids.extend_from_slice(&docids.as_set());
+                }
+                DocIds::Set(Cow::Owned(SetBuf::from_dirty(ids)))
+            },
+        }
+    }
+
+    pub fn difference(include: &DocIds<'_>, exclude: &DocIds<'_>) -> DocIds<'c> {
+        match (include, exclude) {
+            (DocIds::Bitmap(include), DocIds::Bitmap(exclude)) => {
+                DocIds::Bitmap(Cow::Owned(include.as_ref() - exclude.as_ref()))
+            },
+            _ => {
+                let (include, exclude) = (include.as_set(), exclude.as_set());
+                let op = sdset::duo::Difference::new(&include, &exclude);
+                DocIds::Set(Cow::Owned(op.into_set_buf()))
+            },
+        }
+    }
+}
+
+fn bitmaps<'a>(docids: &[&'a DocIds<'_>]) -> Option<Vec<&'a RoaringBitmap>> {
+    docids.iter().map(|docids| match docids {
+        DocIds::Bitmap(bitmap) => Some(bitmap.as_ref()),
+        DocIds::Set(_) => None,
+    })
+    .collect()
+}
+
+pub fn set_to_bitmap(set: &Set<DocId>) -> RoaringBitmap {
+    RoaringBitmap::from_sorted_iter(set.iter().copied()).expect("the set is sorted")
+}
+
+pub fn bitmap_to_set(bitmap: &RoaringBitmap) -> SetBuf<DocId> {
+    SetBuf::new_unchecked(bitmap.iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ids: &[DocId]) -> DocIds<'static> {
+        DocIds::Set(Cow::Owned(SetBuf::from_dirty(ids.to_vec())))
+    }
+
+    fn bitmap(ids: &[DocId]) -> DocIds<'static> {
+        set(ids).into_backend(Backend::Roaring)
+    }
+
+    #[test]
+    fn same_results() {
+        let (a, b, c) = (&[1, 2, 5, 70_000][..], &[2, 3, 5][..], &[5, 70_000][..]);
+
+        for &backend in &[Backend::Sdset, Backend::Roaring] {
+            let docids = |ids| set(ids).into_backend(backend);
+            let (a, b, c) = (docids(a), docids(b), docids(c));
+
+            let and = DocIds::intersection(&[&a, &b, &c]);
+            assert_eq!(and.as_set().as_slice(), &[5]);
+
+            let or = DocIds::union(&[&a, &b, &c]);
+            assert_eq!(or.as_set().as_slice(), &[1, 2, 3, 5, 70_000]);
+
+            let and_not = DocIds::difference(&a, &b);
+            assert_eq!(and_not.into_set().as_slice(), &[1, 70_000]);
+        }
+    }
+
+    #[test]
+    fn bitmaps_context() {
+        use crate::fixtures::{search, titles};
+
+        let mut context = titles(&["hello world", "hello there", "the big world", "hello", "world wide web"]);
+        let queries = ["hello world", "hello -world", "wor", "\"the big\" world", "helo wide"];
+        let documents = |context: &_| -> Vec<Vec<DocId>> {
+            queries.iter().map(|query| search(context, query)).collect()
+        };
+
+        let expected = documents(&context);
+        assert_eq!(context.backend(), Backend::Sdset);
+
+        context.use_bitmaps();
+        assert_eq!(context.backend(), Backend::Roaring);
+        assert!(context.postings_list("hello").unwrap().bitmap.is_some());
+        assert_eq!(documents(&context), expected);
+    }
+
+    #[test]
+    fn mixed_backends() {
+        let and = DocIds::intersection(&[&set(&[1, 2, 3]), &bitmap(&[2, 3, 4])]);
+        assert!(matches!(and, DocIds::Set(_)));
+        assert_eq!(and.as_set().as_slice(), &[2, 3]);
+
+        let or = DocIds::union(&[&bitmap(&[1]), &bitmap(&[3])]);
+        assert!(matches!(or, DocIds::Bitmap(_)));
+        assert_eq!(or.len(), 2);
+    }
+}
@@ -0,0 +1,50 @@
+use std::{error, fmt, io};
+
+use crate::DocId;
+
+// The errors of the documents, synonyms and index files.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Csv(csv::Error),
+    // A document that is not an object, at this line of a
+    // JSON Lines file or at this position of a JSON array.
+    NotAnObject(usize),
+    // A JSON file that is not an array of documents.
+    NotAnArray,
+    InvalidSynonyms(String),
+    InvalidIndex(String),
+    UnknownFormat,
+    TooManyDocuments,
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error { Error::Io(error) }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Error { Error::Json(error) }
+}
+
+impl From<csv::Error> for Error {
+    fn from(error: csv::Error) -> Error { Error::Csv(error) }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Json(e) => write!(f, "invalid JSON: {}", e),
+            Error::Csv(e) => write!(f, "invalid CSV: {}", e),
+            Error::NotAnObject(n) => write!(f, "document {} is not a JSON object", n),
+            Error::NotAnArray => f.write_str("the JSON file is not an array of documents"),
+            Error::InvalidSynonyms(message) => write!(f, "invalid synonyms: {}", message),
+            Error::InvalidIndex(message) => write!(f, "invalid index: {}", message),
+            Error::UnknownFormat => f.write_str("unknown format, expected a .csv, .json or .jsonl file"),
+            Error::TooManyDocuments => write!(f, "more than {} documents", DocId::max_value() as usize + 1),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,183 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+// The statistics of an evaluated node of the query tree.
+#[derive(Debug, Clone)]
+pub struct Explain {
+    pub label: String,
+    pub documents: usize,
+    pub matches: usize,
+    pub cache_hit: bool,
+    pub elapsed: Duration,
+    pub children: Vec<Explain>,
+}
+
+impl Explain {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "label": self.label,
+            "documents": self.documents,
+            "matches": self.matches,
+            "cache_hit": self.cache_hit,
+            "elapsed_us": self.elapsed.as_micros() as u64,
+            "children": self.children.iter().map(Explain::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl fmt::Display for Explain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn pprint_tree(f: &mut fmt::Formatter<'_>, node: &Explain, depth: usize) -> fmt::Result {
+            let Explain { label, documents, matches, cache_hit, elapsed, children } = node;
+            let cached = if *cache_hit { " (cached)" } else { "" };
+
+            if children.is_empty() {
+                write!(f, "{:1$}{2} fetched {3} documents", "", depth * 2, label, documents)?;
+                if *matches != 0 { write!(f, " with {} matches", matches)?; }
+                writeln!(f, " in {:.02?}{}", elapsed, cached)
+            } else {
+                writeln!(f, "{:1$}{2}", "", depth * 2, label)?;
+                children.iter().try_for_each(|c| pprint_tree(f, c, depth + 1))?;
+                writeln!(f, "{:1$}--- {2} fetched {3} documents in {4:.02?}{5}", "", depth * 2, label, documents, elapsed, cached)
+            }
+        }
+
+        pprint_tree(f, self, 0)
+    }
+}
+
+// Collects the statistics of the nodes while the tree is evaluated, a disabled
+// tracer discards them without measuring anything but still counts the nodes.
+pub struct Tracer {
+    enabled: bool,
+    // The children of the nodes being evaluated, the root is at the bottom.
+    stack: Vec<Vec<Explain>>,
+    evaluated: usize,
+    cache_hits: usize,
+}
+
+impl Tracer {
+    pub fn enabled() -> Tracer {
+        Tracer { enabled: true, stack: vec![Vec::new()], evaluated: 0, cache_hits: 0 }
+    }
+
+    pub fn disabled() -> Tracer {
+        Tracer { enabled: false, stack: Vec::new(), evaluated: 0, cache_hits: 0 }
+    }
+
+    // The number of nodes that have been evaluated.
+    pub fn evaluated(&self) -> usize {
+        self.evaluated
+    }
+
+    // The number of nodes that have not been evaluated again as they were in cache.
+    pub fn cache_hits(&self) -> usize {
+        self.cache_hits
+    }
+
+    // The time the evaluation of a node starts, it is not measured when the tracer is disabled.
+    pub fn start(&self) -> Option<Instant> {
+        if self.enabled { Some(Instant::now()) } else { None }
+    }
+
+    // Must be called before evaluating the children of a node.
+    pub fn enter(&mut self) {
+        if self.enabled {
+            self.stack.push(Vec::new());
+        }
+    }
+
+    // Must be called once a node entered has been evaluated.
+    pub fn exit<F>(&mut self, f: F)
+    where F: FnOnce(Vec<Explain>) -> Explain,
+    {
+        self.evaluated += 1;
+        if self.enabled {
+            let children = self.stack.pop().unwrap_or_default();
+            self.push(f(children));
+        }
+    }
+
+    // Records a node that had no children to evaluate, a query.
+    pub fn record<F>(&mut self, f: F)
+    where F: FnOnce() -> Explain,
+    {
+        self.evaluated += 1;
+        if self.enabled {
+            let node = f();
+            self.push(node);
+        }
+    }
+
+    // Records a node that has not been evaluated as it was found in cache.
+    pub fn record_cached<F>(&mut self, f: F)
+    where F: FnOnce() -> Explain,
+    {
+        self.cache_hits += 1;
+        if self.enabled {
+            let node = f();
+            self.push(node);
+        }
+    }
+
+    fn push(&mut self, node: Explain) {
+        match self.stack.last_mut() {
+            Some(siblings) => siblings.push(node),
+            None => self.stack.push(vec![node]),
+        }
+    }
+
+    pub fn into_explain(mut self) -> Option<Explain> {
+        self.stack.pop().and_then(|mut roots| roots.pop())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaf(label: &str, documents: usize) -> Explain {
+        Explain {
+            label: label.to_owned(),
+            documents,
+            matches: documents * 2,
+            cache_hit: false,
+            elapsed: Duration::from_micros(10),
+            children: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn nested_nodes() {
+        let mut tracer = Tracer::enabled();
+
+        tracer.enter();
+        tracer.record(|| leaf("hello", 10));
+        tracer.enter();
+        tracer.record(|| leaf("world", 5));
+        tracer.exit(|children| Explain { children, ..leaf("OR", 5) });
+        tracer.exit(|children| Explain { children, ..leaf("AND", 3) });
+
+        let explain = tracer.into_explain().unwrap();
+        assert_eq!(explain.label, "AND");
+        assert_eq!(explain.children.len(), 2);
+        assert_eq!(explain.children[1].children[0].label, "world");
+
+        let json = explain.to_json();
+        assert_eq!(json["children"][1]["children"][0]["documents"], 5);
+    }
+
+    #[test]
+    fn disabled_tracer() {
+        let mut tracer = Tracer::disabled();
+        assert!(tracer.start().is_none());
+        tracer.enter();
+        tracer.record(|| panic!("a disabled tracer must not build nodes"));
+        tracer.record_cached(|| panic!("a disabled tracer must not build nodes"));
+        tracer.exit(|_| panic!("a disabled tracer must not build nodes"));
+        assert_eq!((tracer.evaluated(), tracer.cache_hits()), (2, 1));
+        assert!(tracer.into_explain().is_none());
+    }
+}
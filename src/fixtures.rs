@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+use crate::indexer::{Document, Indexer};
+use crate::{Context, DocId, create_query_tree, traverse_query_tree};
+
+pub fn document(id: DocId, fields: &[(&str, &str)]) -> Document {
+    let fields = fields.iter().map(|(n, t)| (n.to_string(), t.to_string())).collect();
+    Document { id, fields }
+}
+
+// Indexes the documents, the attributes of the context are the names of their fields.
+pub fn context(documents: &[Document]) -> Context {
+    let mut indexer = Indexer::new();
+    for document in documents {
+        indexer.index_document(document);
+    }
+
+    let attributes = indexer.attributes().to_vec();
+    let mut context = Context::new(HashMap::new(), indexer.build());
+    context.attributes = attributes;
+    context
+}
+
+// Indexes documents only made of a title, their ids are their positions.
+pub fn titles(titles: &[&str]) -> Context {
+    let documents: Vec<_> = titles.iter().enumerate().map(|(id, title)| {
+        document(id as DocId, &[("title", title)])
+    })
+    .collect();
+
+    context(&documents)
+}
+
+// The documents matching the query, the tree is built with the default options.
+pub fn search(ctx: &Context, query: &str) -> Vec<DocId> {
+    let (tree, _) = create_query_tree(ctx, query);
+    traverse_query_tree(ctx, &tree).docids.to_vec()
+}
@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use sdset::SetBuf;
+
+use crate::query_parser::{normalize, tokenize};
+use crate::{AttributeId, DocId, DocIndex, PostingsList, WordIndex, encode_position};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    pub id: DocId,
+    // The name and the text of the fields, in the order they must be indexed.
+    pub fields: Vec<(String, String)>,
+}
+
+// Collects the positions of the words of the documents, every field
+// name is given an attribute id in the order they are first seen.
+// The names are normalized like the queries for the fields of the queries to be found.
+#[derive(Debug, Default)]
+pub struct Indexer {
+    attributes: Vec<String>,
+    words: HashMap<String, Vec<DocIndex>>,
+}
+
+impl Indexer {
+    pub fn new() -> Indexer {
+        Indexer::default()
+    }
+
+    // Declares the order of the attributes, the first ones are the most important.
+    pub fn with_attributes(attributes: &[String]) -> Indexer {
+        let mut indexer = Indexer::new();
+        for name in attributes {
+            indexer.attribute_id(name);
+        }
+        indexer
+    }
+
+    pub fn attributes(&self) -> &[String] {
+        &self.attributes
+    }
+
+    fn attribute_id(&mut self, name: &str) -> Option<AttributeId> {
+        let name = normalize(name);
+        let id = match self.attributes.iter().position(|n| *n == name) {
+            Some(id) => id,
+            None => {
+                self.attributes.push(name);
+                self.attributes.len() - 1
+            },
+        };
+
+        if id > AttributeId::max_value() as usize { None } else { Some(id as AttributeId) }
+    }
+
+    // The words that come after the maximum word index of an attribute are ignored.
+    pub fn index_document(&mut self, document: &Document) {
+        for (name, text) in &document.fields {
+            let attribute = match self.attribute_id(name) {
+                Some(attribute) => attribute,
+                None => continue,
+            };
+
+            for (word_index, word) in tokenize(text).into_iter().enumerate() {
+                if word_index > WordIndex::max_value() as usize { break }
+                let position = encode_position(attribute, word_index as WordIndex);
+                let matches = self.words.entry(word).or_default();
+                matches.push(DocIndex { docid: document.id, position });
+            }
+        }
+    }
+
+    pub fn build(self) -> HashMap<String, PostingsList> {
+        self.words.into_iter().map(|(word, matches)| {
+            let matches = SetBuf::from_dirty(matches);
+
+            let mut docids: Vec<_> = matches.iter().map(|m| m.docid).collect();
+            docids.dedup();
+            let docids = SetBuf::new_unchecked(docids);
+
+            (word, PostingsList { docids, matches })
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use big_s::S;
+    use crate::fixtures::{context, document, search};
+    use crate::Position;
+
+    fn matches(list: &PostingsList) -> Vec<(DocId, Position)> {
+        list.matches.iter().map(|m| (m.docid, m.position)).collect()
+    }
+
+    #[test]
+    fn postings_of_documents() {
+        let mut indexer = Indexer::new();
+        indexer.index_document(&document(1, &[("title", "Hello World"), ("body", "the \"world\" is big")]));
+        indexer.index_document(&document(0, &[("title", "hello  there")]));
+
+        let postings = indexer.build();
+
+        let hello = &postings["hello"];
+        assert_eq!(hello.docids.as_slice(), &[0, 1]);
+        assert_eq!(matches(hello), vec![(0, 0), (1, 0)]);
+
+        let world = &postings["world"];
+        assert_eq!(world.docids.as_slice(), &[1]);
+        assert_eq!(matches(world), vec![(1, 1), (1, encode_position(1, 1))]);
+
+        assert!(!postings.contains_key("World"));
+        assert!(!postings.contains_key("\"world\""));
+    }
+
+    #[test]
+    fn query_indexed_documents() {
+        let ctx = context(&[
+            document(0, &[("title", "Hello World")]),
+            document(1, &[("title", "world, hello")]),
+            document(2, &[("title", "hello there")]),
+        ]);
+
+        assert_eq!(search(&ctx, "HELLO \"world\""), vec![0]);
+    }
+
+    #[test]
+    fn declared_attributes() {
+        let mut indexer = Indexer::with_attributes(&[S("Title"), S("body"), S("title")]);
+        indexer.index_document(&document(0, &[("body", "hello"), ("TAGS", "hello")]));
+        indexer.index_document(&document(1, &[("title", "hello")]));
+
+        assert_eq!(indexer.attributes(), &[S("title"), S("body"), S("tags")]);
+        assert_eq!(matches(&indexer.build()["hello"]), vec![
+            (0, encode_position(1, 0)),
+            (0, encode_position(2, 0)),
+            (1, encode_position(0, 0)),
+        ]);
+    }
+}
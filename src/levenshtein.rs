@@ -0,0 +1,109 @@
+use std::cmp;
+
+// The maximum number of typos a word can be corrected with.
+pub const MAX_TYPOS: u8 = 2;
+
+// The number of typos allowed depends on the length of the word,
+// short words are too ambiguous to be corrected.
+pub fn typo_budget(word: &str) -> u8 {
+    match word.chars().count() {
+        0..=4 => 0,
+        5..=8 => 1,
+        _ => MAX_TYPOS,
+    }
+}
+
+// Returns the Levenshtein distance between `a` and `b`
+// or `None` if it is greater than `max`.
+pub fn distance(a: &str, b: &str, max: u8) -> Option<u8> {
+    levenshtein(a, b, max, false)
+}
+
+// Returns the smallest Levenshtein distance between `a` and
+// any prefix of `b` or `None` if it is greater than `max`.
+pub fn prefix_distance(a: &str, b: &str, max: u8) -> Option<u8> {
+    levenshtein(a, b, max, true)
+}
+
+fn levenshtein(a: &str, b: &str, max: u8, prefix: bool) -> Option<u8> {
+    let a: Vec<_> = a.chars().collect();
+    let b: Vec<_> = b.chars().collect();
+    let max = max as usize;
+
+    let length_diff = if a.len() > b.len() { a.len() - b.len() } else if prefix { 0 } else { b.len() - a.len() };
+    if length_diff > max { return None }
+
+    // We only keep a single row of the matrix, `diagonal` holds
+    // the value of the previous row at the previous column.
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        let mut row_min = row[0];
+
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == cb { 0 } else { 1 };
+            let value = cmp::min(cmp::min(row[j + 1], row[j]) + 1, diagonal + cost);
+            diagonal = row[j + 1];
+            row[j + 1] = value;
+            row_min = cmp::min(row_min, value);
+        }
+
+        // The distance can only grow from here.
+        if row_min > max { return None }
+    }
+
+    // The last row gives the distance with every prefix of `b`.
+    let distance = if prefix { row.into_iter().min().unwrap() } else { row[b.len()] };
+    if distance <= max { Some(distance as u8) } else { None }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget() {
+        assert_eq!(typo_budget("hell"), 0);
+        assert_eq!(typo_budget("hello"), 1);
+        assert_eq!(typo_budget("patterns"), 1);
+        assert_eq!(typo_budget("beautiful"), 2);
+        assert_eq!(typo_budget("épée"), 0);
+    }
+
+    #[test]
+    fn simple_distances() {
+        assert_eq!(distance("hello", "hello", 0), Some(0));
+        assert_eq!(distance("hello", "hell", 1), Some(1));
+        assert_eq!(distance("hello", "hallo", 1), Some(1));
+        assert_eq!(distance("hello", "helol", 2), Some(2));
+        assert_eq!(distance("world", "word", 1), Some(1));
+        assert_eq!(distance("kitten", "sitting", 3), Some(3));
+    }
+
+    #[test]
+    fn exceeding_max() {
+        assert_eq!(distance("hello", "hell", 0), None);
+        assert_eq!(distance("hello", "help", 1), None);
+        assert_eq!(distance("kitten", "sitting", 2), None);
+        assert_eq!(distance("a", "abcd", 2), None);
+    }
+
+    #[test]
+    fn unicode() {
+        assert_eq!(distance("café", "cafe", 1), Some(1));
+        assert_eq!(distance("naïve", "naive", 1), Some(1));
+    }
+
+    #[test]
+    fn prefix_distances() {
+        assert_eq!(prefix_distance("hel", "hello", 0), Some(0));
+        assert_eq!(prefix_distance("hello", "helloworld", 0), Some(0));
+        assert_eq!(prefix_distance("hallo", "helloworld", 1), Some(1));
+        assert_eq!(prefix_distance("wrld", "world2020", 1), Some(1));
+        assert_eq!(prefix_distance("", "world", 0), Some(0));
+        assert_eq!(prefix_distance("hello", "hel", 1), None);
+        assert_eq!(prefix_distance("world", "hello", 2), None);
+    }
+}
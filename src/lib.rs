@@ -0,0 +1,1386 @@
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::ops::{Range, RangeFrom};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+use std::{cmp, fmt, iter::once};
+
+use big_s::S;
+use docids::{Backend, DocIds};
+use explain::{Explain, Tracer};
+use fst::{Automaton, IntoStreamer, Streamer};
+use fst::automaton::{Levenshtein, Str};
+use itertools::{EitherOrBoth, merge_join_by};
+use query_parser::{ParsedQuery, QueryToken, parse_query};
+use query_words_mapper::QueryWordsMapper;
+use roaring::RoaringBitmap;
+use store::{Bytes, Store};
+use synonyms::Synonyms;
+use word_splitter::WordSplitter;
+use sdset::{Set, SetBuf, SetOperation};
+
+pub mod bucket_sort;
+pub mod criterion;
+pub mod docids;
+pub mod error;
+pub mod explain;
+pub mod indexer;
+pub mod levenshtein;
+pub mod loader;
+pub mod optimizer;
+pub mod proximity;
+pub mod query_parser;
+pub mod store;
+pub mod synonyms;
+pub mod top_k;
+pub mod word_splitter;
+mod query_words_mapper;
+#[cfg(test)]
+mod fixtures;
+
+// The children are shared, the identical subtrees can be the same
+// node once the tree has been optimized and the tree becomes a DAG.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Operation {
+    And(Vec<Rc<Operation>>),
+    Or(Vec<Rc<Operation>>),
+    // The documents of the first operation without the ones of the second.
+    AndNot(Rc<Operation>, Rc<Operation>),
+    Query(Query),
+}
+
+impl fmt::Debug for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn pprint_tree(f: &mut fmt::Formatter<'_>, op: &Operation, depth: usize) -> fmt::Result {
+            match op {
+                Operation::And(children) => {
+                    writeln!(f, "{:1$}AND", "", depth * 2)?;
+                    children.iter().try_for_each(|c| pprint_tree(f, c, depth + 1))
+                },
+                Operation::Or(children) => {
+                    writeln!(f, "{:1$}OR", "", depth * 2)?;
+                    children.iter().try_for_each(|c| pprint_tree(f, c, depth + 1))
+                },
+                Operation::AndNot(include, exclude) => {
+                    writeln!(f, "{:1$}ANDNOT", "", depth * 2)?;
+                    pprint_tree(f, include, depth + 1)?;
+                    pprint_tree(f, exclude, depth + 1)
+                },
+                Operation::Query(query) => writeln!(f, "{:2$}{:?}", "", query, depth * 2),
+            }
+        }
+
+        pprint_tree(f, self, 0)
+    }
+}
+
+impl Operation {
+    pub fn and(ops: Vec<Operation>) -> Operation {
+        Operation::And(ops.into_iter().map(Rc::new).collect())
+    }
+
+    pub fn or(ops: Vec<Operation>) -> Operation {
+        Operation::Or(ops.into_iter().map(Rc::new).collect())
+    }
+
+    pub fn and_not(include: Operation, exclude: Operation) -> Operation {
+        Operation::AndNot(Rc::new(include), Rc::new(exclude))
+    }
+
+    pub fn tolerant(id: QueryId, prefix: bool, s: &str) -> Operation {
+        Operation::Query(Query { id, prefix, field: None, split: false, kind: QueryKind::Tolerant(s.to_string()) })
+    }
+
+    pub fn exact(id: QueryId, prefix: bool, s: &str) -> Operation {
+        Operation::Query(Query { id, prefix, field: None, split: false, kind: QueryKind::Exact(s.to_string()) })
+    }
+
+    pub fn phrase(id: QueryId, prefix: bool, words: &[String]) -> Operation {
+        Operation::Query(Query { id, prefix, field: None, split: false, kind: QueryKind::Phrase(words.to_vec()) })
+    }
+
+    // The words a query word has been split into, searched as a phrase.
+    pub fn split(id: QueryId, prefix: bool, words: &[String]) -> Operation {
+        Operation::Query(Query { id, prefix, field: None, split: true, kind: QueryKind::Phrase(words.to_vec()) })
+    }
+
+    // Restricts the queries of the operation to the field.
+    pub fn in_field(&self, name: &str) -> Operation {
+        let in_field = |ops: &[Rc<Operation>]| ops.iter().map(|op| op.in_field(name)).collect();
+        match self {
+            Operation::And(ops) => Operation::and(in_field(ops)),
+            Operation::Or(ops) => Operation::or(in_field(ops)),
+            Operation::AndNot(include, exclude) => Operation::and_not(include.in_field(name), exclude.in_field(name)),
+            Operation::Query(query) => {
+                Operation::Query(Query { field: Some(name.to_owned()), ..query.clone() })
+            },
+        }
+    }
+}
+
+pub type QueryId = usize;
+
+#[derive(Clone, Eq)]
+pub struct Query {
+    pub id: QueryId,
+    pub prefix: bool,
+    // The name of the field the query must be found in, any field if none.
+    pub field: Option<String>,
+    // Whether the phrase comes from the split of a query word, its matches are not exact.
+    pub split: bool,
+    pub kind: QueryKind,
+}
+
+impl PartialEq for Query {
+    fn eq(&self, other: &Self) -> bool {
+        self.prefix == other.prefix
+            && self.field == other.field
+            && self.split == other.split
+            && self.kind == other.kind
+    }
+}
+
+impl Hash for Query {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.prefix.hash(state);
+        self.field.hash(state);
+        self.split.hash(state);
+        self.kind.hash(state);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum QueryKind {
+    Tolerant(String),
+    Exact(String),
+    Phrase(Vec<String>),
+}
+
+impl fmt::Debug for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Query { id, prefix, field, split, kind } = self;
+        let prefix = if *prefix { String::from("Prefix") } else { String::default() };
+        let name = match kind {
+            QueryKind::Exact(_) => "Exact",
+            QueryKind::Tolerant(_) => "Tolerant",
+            QueryKind::Phrase(_) => "Phrase",
+        };
+
+        let mut builder = f.debug_struct(&(prefix + name));
+        builder.field("id", &id);
+        match kind {
+            QueryKind::Exact(word) | QueryKind::Tolerant(word) => builder.field("word", &word),
+            QueryKind::Phrase(words) => builder.field("words", &words),
+        };
+
+        if let Some(field) = field {
+            builder.field("field", &field);
+        }
+
+        if *split {
+            builder.field("split", split);
+        }
+
+        builder.finish()
+    }
+}
+
+pub type DocId = u32;
+pub type Position = u32;
+pub type AttributeId = u16;
+pub type WordIndex = u16;
+
+// A position stores the attribute the word is in in the high bits
+// and the index of the word in this attribute in the low bits.
+pub fn encode_position(attribute: AttributeId, word_index: WordIndex) -> Position {
+    (Position::from(attribute) << 16) | Position::from(word_index)
+}
+
+pub fn attribute_of(position: Position) -> AttributeId {
+    (position >> 16) as AttributeId
+}
+
+pub fn word_index_of(position: Position) -> WordIndex {
+    position as WordIndex
+}
+
+// A word at a position in a document, it is `repr(C)`
+// to be borrowed from the memory mapped index files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(C)]
+pub struct DocIndex {
+    pub docid: DocId,
+    pub position: Position,
+}
+
+#[derive(Debug, Default)]
+pub struct PostingsList {
+    pub docids: SetBuf<DocId>,
+    pub matches: SetBuf<DocIndex>,
+}
+
+// A postings list borrowed from the context, it comes
+// from the memory or from a memory mapped index file.
+#[derive(Debug, Clone, Copy)]
+pub struct PostingsRef<'a> {
+    pub docids: &'a Set<DocId>,
+    pub matches: &'a Set<DocIndex>,
+    // The docids compressed, only when the context uses the bitmaps.
+    pub bitmap: Option<&'a RoaringBitmap>,
+}
+
+impl<'a> From<&'a PostingsList> for PostingsRef<'a> {
+    fn from(list: &'a PostingsList) -> PostingsRef<'a> {
+        PostingsRef { docids: list.docids.as_set(), matches: list.matches.as_set(), bitmap: None }
+    }
+}
+
+#[derive(Debug)]
+pub struct Context {
+    synonyms: Synonyms,
+    // The sorted words that have postings, used to search them by prefix or with
+    // Levenshtein automatons. The values are the indexes of their postings lists.
+    words: fst::Map<Bytes>,
+    store: Store,
+    // The maximum number of words a prefix query can be expanded to,
+    // the ones with the fewest typos are kept first.
+    pub max_prefix_expansions: Option<usize>,
+    // The names of the attributes in the order of their ids,
+    // used to restrict the queries to a field.
+    pub attributes: Vec<String>,
+    // The words that are not required to match, the documents
+    // containing them are still found to be ranked by the criteria.
+    pub stop_words: HashSet<String>,
+    // The docids of the postings lists as bitmaps, in the order of the store.
+    bitmaps: Option<Vec<RoaringBitmap>>,
+}
+
+impl Context {
+    pub fn new(
+        synonyms: Synonyms,
+        postings: HashMap<String, PostingsList>,
+    ) -> Context
+    {
+        let mut postings: Vec<_> = postings.into_iter().collect();
+        postings.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+        let mut builder = fst::MapBuilder::memory();
+        for (i, (word, _)) in postings.iter().enumerate() {
+            builder.insert(word, i as u64).unwrap();
+        }
+
+        let words = fst::Map::new(Bytes::Owned(builder.into_inner().unwrap())).unwrap();
+        let store = Store::Memory(postings.into_iter().map(|(_, list)| list).collect());
+
+        Context::from_parts(synonyms, words, store)
+    }
+
+    fn from_parts(synonyms: Synonyms, words: fst::Map<Bytes>, store: Store) -> Context {
+        Context {
+            synonyms,
+            words,
+            store,
+            max_prefix_expansions: None,
+            attributes: Vec::new(),
+            stop_words: HashSet::new(),
+            bitmaps: None,
+        }
+    }
+
+    // Computes the bitmaps of all the postings lists, the
+    // query trees are then evaluated with the bitmaps.
+    pub fn use_bitmaps(&mut self) {
+        let store = &self.store;
+        let bitmaps = (0..store.len() as u64).map(|i| {
+            store.get(i).map_or_else(RoaringBitmap::new, |list| docids::set_to_bitmap(list.docids))
+        });
+        self.bitmaps = Some(bitmaps.collect());
+    }
+
+    pub fn backend(&self) -> Backend {
+        if self.bitmaps.is_some() { Backend::Roaring } else { Backend::Sdset }
+    }
+
+    fn postings_at(&self, index: u64) -> Option<PostingsRef<'_>> {
+        let bitmap = self.bitmaps.as_ref().and_then(|b| b.get(index as usize));
+        self.store.get(index).map(|list| PostingsRef { bitmap, ..list })
+    }
+
+    pub fn postings_list(&self, word: &str) -> Option<PostingsRef<'_>> {
+        self.words.get(word).and_then(|index| self.postings_at(index))
+    }
+
+    // Whether the word is in the dictionary, or a prefix of one of its words.
+    pub fn contains_word(&self, word: &str, prefix: bool) -> bool {
+        if prefix {
+            self.words.search(Str::new(word).starts_with()).into_stream().next().is_some()
+        } else {
+            self.words.contains_key(word)
+        }
+    }
+
+    // Returns the words of the dictionary accepted by the automaton
+    // along with their postings lists, in lexicographic order.
+    fn search_words<A: Automaton>(&self, automaton: A) -> Vec<(String, PostingsRef<'_>)> {
+        let mut stream = self.words.search(automaton).into_stream();
+        let mut words = Vec::new();
+
+        while let Some((word, index)) = stream.next() {
+            let word = String::from_utf8(word.to_vec()).unwrap();
+            words.extend(self.postings_at(index).map(|list| (word, list)));
+        }
+
+        words
+    }
+}
+
+fn fetch_synonyms<S: AsRef<str>>(ctx: &Context, words: &[S]) -> Vec<Vec<String>> {
+    let words: Vec<_> = words.iter().map(|s| s.as_ref().to_owned()).collect(); // TODO ugly
+    ctx.synonyms.get(&words).cloned().unwrap_or_default()
+}
+
+// Whether the operation can match documents, the tolerant queries
+// are kept as their typo corrections are only known when evaluated.
+fn can_match(ctx: &Context, operation: &Operation) -> bool {
+    match operation {
+        Operation::And(ops) => ops.iter().all(|op| can_match(ctx, op)),
+        Operation::Or(ops) => ops.iter().any(|op| can_match(ctx, op)),
+        Operation::AndNot(include, _) => can_match(ctx, include),
+        Operation::Query(Query { prefix, field, kind, .. }) => {
+            let known_field = field.as_ref().map_or(true, |f| ctx.attributes.contains(f));
+            known_field && match kind {
+                QueryKind::Tolerant(_) => true,
+                QueryKind::Exact(word) => ctx.contains_word(word, *prefix),
+                QueryKind::Phrase(words) => words.iter().all(|w| ctx.contains_word(w, false)),
+            }
+        },
+    }
+}
+
+fn create_operation<I, F>(iter: I, f: F) -> Operation
+where I: IntoIterator<Item=Operation>,
+      F: Fn(Vec<Operation>) -> Operation,
+{
+    let mut iter = iter.into_iter();
+    match (iter.next(), iter.next()) {
+        (Some(first), None) => first,
+        (first, second) => f(first.into_iter().chain(second).chain(iter).collect()),
+    }
+}
+
+#[derive(Debug)]
+pub struct QueryTreeOptions {
+    // The maximum number of consecutive words searched together,
+    // as synonyms or concatenated in a single word.
+    pub max_ngram: usize,
+    // Whether the consecutive words are also searched concatenated, like `helloworld`.
+    pub concatenations: bool,
+    // Whether the concatenations of the last words are searched as prefixes.
+    pub prefix_concatenations: bool,
+    // Whether the concatenations are only kept when they are in the dictionary,
+    // as a prefix of a word of the dictionary when they are searched as prefixes.
+    pub concatenations_in_dictionary: bool,
+    // Whether the alternatives that can't match any document are dropped while the tree
+    // is built: the synonyms, concatenations and phrases with words absent from the dictionary.
+    pub prune_absent_words: bool,
+    // Whether the tree is simplified and its identical subtrees shared.
+    pub optimize: bool,
+    // Splits the query words into words of the dictionary, searched as phrases.
+    pub word_splitter: WordSplitter,
+}
+
+impl Default for QueryTreeOptions {
+    fn default() -> QueryTreeOptions {
+        QueryTreeOptions {
+            max_ngram: 3,
+            concatenations: true,
+            prefix_concatenations: true,
+            concatenations_in_dictionary: false,
+            prune_absent_words: false,
+            optimize: true,
+            word_splitter: WordSplitter::default(),
+        }
+    }
+}
+
+// The operation of a token searched without alternatives.
+fn strict_operation(id: QueryId, token: &QueryToken) -> Operation {
+    match token {
+        QueryToken::Fielded(field, token) => strict_operation(id, token).in_field(field),
+        token => match token.words() {
+            [word] => Operation::exact(id, false, word),
+            words => Operation::phrase(id, false, words),
+        },
+    }
+}
+
+// Gives the alternatives of the n-grams ids that don't overlap the ones of the other n-grams,
+// an n-gram found again in another branch of the tree is given the same ids.
+struct NgramIds {
+    next: QueryId,
+    starts: HashMap<(QueryId, usize), QueryId>,
+}
+
+impl NgramIds {
+    fn new(next: QueryId) -> NgramIds {
+        NgramIds { next, starts: HashMap::new() }
+    }
+
+    // The ids of the alternatives of the n-gram starting at the word id.
+    fn start(&mut self, id: QueryId, ngram: usize) -> RangeFrom<QueryId> {
+        let next = self.next;
+        *self.starts.entry((id, ngram)).or_insert(next)..
+    }
+
+    // The ids that were not taken by the alternatives of the n-gram are given to the next ones.
+    fn end(&mut self, ids: RangeFrom<QueryId>) {
+        self.next = cmp::max(self.next, ids.start);
+    }
+}
+
+fn is_stop_word(ctx: &Context, token: &QueryToken) -> bool {
+    token.free().map_or(false, |word| ctx.stop_words.contains(word))
+}
+
+pub fn create_query_tree(ctx: &Context, query: &str) -> (Operation, HashMap<QueryId, Range<usize>>) {
+    create_query_tree_with_options(ctx, query, &QueryTreeOptions::default())
+}
+
+pub fn create_query_tree_with_options(
+    ctx: &Context,
+    query: &str,
+    options: &QueryTreeOptions,
+) -> (Operation, HashMap<QueryId, Range<usize>>)
+{
+    let ParsedQuery { tokens, excluded } = parse_query(query, &ctx.attributes);
+
+    // Every word of a token is given its own id.
+    let mut next_id = 0;
+    let mut with_ids = |tokens: Vec<QueryToken>| -> Vec<_> {
+        tokens.into_iter().map(|token| {
+            let id = next_id;
+            next_id += token.words().len();
+            (id, token)
+        }).collect()
+    };
+
+    // The excluded words are given ids after the included ones,
+    // they are not declared to the mapper as they are never highlighted.
+    let tokens = with_ids(tokens);
+    let excluded = with_ids(excluded);
+
+    let mut mapper = QueryWordsMapper::new(tokens.iter().flat_map(|(_, t)| t.words()));
+    let mut ngram_ids = NgramIds::new(next_id);
+
+    fn create_inner(
+        ctx: &Context,
+        options: &QueryTreeOptions,
+        mapper: &mut QueryWordsMapper,
+        ngram_ids: &mut NgramIds,
+        words: &[(QueryId, QueryToken)],
+    ) -> Vec<Operation>
+    {
+        let prune = options.prune_absent_words;
+        let mut alts = Vec::new();
+
+        for ngram in 1..=cmp::max(options.max_ngram, 1) {
+            if let Some(group) = words.get(..ngram) {
+                // Quoted and fielded words can't be part of an n-gram.
+                if ngram > 1 && group.iter().any(|(_, t)| t.free().is_none()) { break }
+
+                let tail = &words[ngram..];
+                let is_last = tail.is_empty();
+
+                // The alternatives are paired with the words they must be declared with,
+                // the original words and the quoted and fielded ones are already known.
+                let mut group_alts = Vec::new();
+                match group {
+                    [(id, QueryToken::Quoted(words))] => {
+                        // The user asked for these exact words, we don't search for alternatives.
+                        let operation = match words.as_slice() {
+                            [word] => Operation::exact(*id, false, word),
+                            words => Operation::phrase(*id, false, words),
+                        };
+                        group_alts.push((operation, None));
+                    },
+                    [(id, QueryToken::Fielded(field, token))] => {
+                        // The words are only searched in the field, without synonyms.
+                        let operation = match &**token {
+                            QueryToken::Free(word) => Operation::tolerant(*id, is_last, word),
+                            token => strict_operation(*id, token),
+                        };
+                        group_alts.push((operation.in_field(field), None));
+                    },
+                    [(id, QueryToken::Free(word))] => {
+                        let mut idgen = ngram_ids.start(*id, ngram);
+                        let range = (*id)..id+1;
+
+                        let phrases: Vec<_> = options.word_splitter.split(ctx, word).into_iter().map(|words| {
+                            let id = idgen.next().unwrap();
+                            idgen.nth(words.len() - 2);
+                            let words: Vec<_> = words.into_iter().map(ToOwned::to_owned).collect();
+                            (Operation::split(id, is_last, &words), Some((range.clone(), id, words)))
+                        })
+                        .collect();
+
+                        let synonyms = fetch_synonyms(ctx, &[word]).into_iter().map(|alts| {
+                            let id = idgen.next().unwrap();
+
+                            let mut idgen = once(id).chain(&mut idgen);
+                            let iter = alts.iter().map(|w| {
+                                let id = idgen.next().unwrap();
+                                Operation::exact(id, false, w)
+                            });
+
+                            (create_operation(iter, Operation::and), Some((range.clone(), id, alts)))
+                        });
+
+                        let original = Operation::tolerant(*id, is_last, word);
+
+                        group_alts.push((original, None));
+                        group_alts.extend(synonyms.chain(phrases));
+                        ngram_ids.end(idgen);
+                    },
+                    words => {
+                        let id = words[0].0;
+                        let mut idgen = ngram_ids.start(id, ngram);
+                        let range = id..id+ngram;
+
+                        let words: Vec<_> = words.iter().filter_map(|(_, t)| t.free()).collect();
+
+                        for synonym in fetch_synonyms(ctx, &words) {
+                            let id = idgen.next().unwrap();
+
+                            let mut idgen = once(id).chain(&mut idgen);
+                            let iter = synonym.iter().map(|s| {
+                                let id = idgen.next().unwrap();
+                                Operation::exact(id, false, s)
+                            });
+
+                            let operation = create_operation(iter, Operation::and);
+                            group_alts.push((operation, Some((range.clone(), id, synonym))));
+                        }
+
+                        let concat = words.concat();
+                        let prefix = is_last && options.prefix_concatenations;
+                        let known = !options.concatenations_in_dictionary || ctx.contains_word(&concat, prefix);
+
+                        if options.concatenations && known {
+                            let id = idgen.next().unwrap();
+                            let operation = Operation::exact(id, prefix, &concat);
+                            group_alts.push((operation, Some((range.clone(), id, vec![concat]))));
+                        }
+
+                        ngram_ids.end(idgen);
+                    }
+                }
+
+                // The synonyms, concatenations and phrases absent from the dictionary are dropped.
+                if prune { group_alts.retain(|(op, _)| can_match(ctx, op)) }
+
+                // The n-gram has no alternatives that can match, its synonyms
+                // and concatenation are not searched or absent from the dictionary.
+                if group_alts.is_empty() { continue }
+
+                let (group_alts, declarations): (Vec<_>, Vec<_>) = group_alts.into_iter().unzip();
+                let group_op = create_operation(group_alts, Operation::or);
+
+                let operation = if tail.is_empty() {
+                    group_op
+                } else {
+                    let tail_ops = create_inner(ctx, options, mapper, ngram_ids, tail);
+                    if tail_ops.is_empty() { continue }
+
+                    let tail_op = create_operation(tail_ops, Operation::or);
+                    let both = Operation::and(vec![group_op.clone(), tail_op.clone()]);
+
+                    // The stop words are optional, they are only required when
+                    // there is no other word to search for.
+                    let group_is_stop = match group {
+                        [(_, token)] => is_stop_word(ctx, token),
+                        _ => false,
+                    };
+                    let tail_is_stop = tail.iter().all(|(_, t)| is_stop_word(ctx, t));
+
+                    match (group_is_stop, tail_is_stop) {
+                        (true, false) => Operation::or(vec![both, tail_op]),
+                        (false, true) => Operation::or(vec![both, group_op]),
+                        _ => both,
+                    }
+                };
+
+                // The words are only declared once the n-gram is in the tree,
+                // the mapper must only know the words that are in the tree.
+                for (range, id, words) in declarations.into_iter().flatten() {
+                    mapper.declare(range, id, words);
+                }
+
+                alts.push(operation);
+            }
+        }
+
+        alts
+    }
+
+    let operation = Operation::or(create_inner(ctx, options, &mut mapper, &mut ngram_ids, &tokens));
+
+    let excluded = excluded.iter().map(|(id, token)| strict_operation(*id, token));
+
+    let operation = match create_operation(excluded, Operation::or) {
+        Operation::Or(ops) if ops.is_empty() => operation,
+        exclude => Operation::and_not(operation, exclude),
+    };
+
+    let operation = if options.optimize { optimizer::optimize(&operation) } else { operation };
+
+    let mapping = mapper.mapping();
+
+    (operation, mapping)
+}
+
+// A word of the dictionary that matched a query,
+// the original word itself or one of its typo corrections.
+#[derive(Debug, Clone)]
+pub struct Derivation<'c> {
+    pub word: String,
+    pub typos: u8,
+    pub matches: Cow<'c, Set<DocIndex>>,
+}
+
+pub struct QueryResult<'q, 'c> {
+    pub docids: Cow<'c, Set<DocId>>,
+    pub queries: HashMap<&'q Query, Vec<Derivation<'c>>>,
+}
+
+type Postings<'q, 'c> = HashMap<&'q Query, Vec<Derivation<'c>>>;
+type Cache<'o, 'c> = HashMap<&'o Operation, DocIds<'c>>;
+
+// The docids of the evaluated operations and the derivations of the evaluated queries,
+// kept to evaluate the same tree again with more typos without starting over.
+pub(crate) struct Evaluation<'o, 'c> {
+    cache: Cache<'o, 'c>,
+    postings: Postings<'o, 'c>,
+}
+
+impl<'o, 'c> Evaluation<'o, 'c> {
+    pub(crate) fn new() -> Evaluation<'o, 'c> {
+        Evaluation { cache: Cache::new(), postings: Postings::new() }
+    }
+
+    pub(crate) fn queries(&self) -> &HashMap<&'o Query, Vec<Derivation<'c>>> {
+        &self.postings
+    }
+
+    // Forgets the operations and the queries that can match more
+    // documents when evaluated with more than `max_typos` typos.
+    pub(crate) fn forget_typos(&mut self, max_typos: u8) {
+        self.cache.retain(|op, _| !depends_on_typos(op, max_typos));
+        self.postings.retain(|query, _| !query_depends_on_typos(query, max_typos));
+    }
+}
+
+pub fn traverse_query_tree<'a, 'c>(ctx: &'c Context, tree: &'a Operation) -> QueryResult<'a, 'c> {
+    traverse_query_tree_with_typos(ctx, tree, levenshtein::MAX_TYPOS)
+}
+
+// Evaluates the tree allowing at most `max_typos` typos per word,
+// the typo budget of the words still applies.
+pub fn traverse_query_tree_with_typos<'a, 'c>(
+    ctx: &'c Context,
+    tree: &'a Operation,
+    max_typos: u8,
+) -> QueryResult<'a, 'c>
+{
+    let mut evaluation = Evaluation::new();
+    let docids = traverse(ctx, tree, max_typos, &mut Tracer::disabled(), &mut evaluation);
+    QueryResult { docids, queries: evaluation.postings }
+}
+
+// Evaluates the tree like `traverse_query_tree` and returns
+// the statistics of every node that has been evaluated.
+pub fn explain_query_tree<'a, 'c>(ctx: &'c Context, tree: &'a Operation) -> (QueryResult<'a, 'c>, Explain) {
+    let mut tracer = Tracer::enabled();
+    let mut evaluation = Evaluation::new();
+    let docids = traverse(ctx, tree, levenshtein::MAX_TYPOS, &mut tracer, &mut evaluation);
+    let explain = tracer.into_explain().expect("the root node has been traced");
+    (QueryResult { docids, queries: evaluation.postings }, explain)
+}
+
+// Whether the operation can match more documents with more than `max_typos` typos per word.
+fn depends_on_typos(operation: &Operation, max_typos: u8) -> bool {
+    match operation {
+        Operation::And(ops) | Operation::Or(ops) => ops.iter().any(|op| depends_on_typos(op, max_typos)),
+        Operation::AndNot(include, exclude) => {
+            depends_on_typos(include, max_typos) || depends_on_typos(exclude, max_typos)
+        },
+        Operation::Query(query) => query_depends_on_typos(query, max_typos),
+    }
+}
+
+fn query_depends_on_typos(query: &Query, max_typos: u8) -> bool {
+    match &query.kind {
+        QueryKind::Tolerant(word) => levenshtein::typo_budget(word) > max_typos,
+        QueryKind::Exact(_) | QueryKind::Phrase(_) => false,
+    }
+}
+
+// A rough estimate of the number of documents an operation matches, used to evaluate the
+// most selective children of an `And` first. The prefix queries and the queries allowed to
+// have typos, that can be expanded to many words, are considered the most expensive.
+fn estimate(ctx: &Context, max_typos: u8, operation: &Operation) -> usize {
+    let frequency = |word: &str| ctx.postings_list(word).map_or(0, |pl| pl.docids.len());
+    let child = |op: &Rc<Operation>| estimate(ctx, max_typos, op);
+
+    match operation {
+        Operation::And(ops) => ops.iter().map(child).min().unwrap_or(0),
+        Operation::Or(ops) => ops.iter().map(child).fold(0, usize::saturating_add),
+        Operation::AndNot(include, _) => child(include),
+        Operation::Query(query) => match &query.kind {
+            QueryKind::Tolerant(_) | QueryKind::Exact(_) if query.prefix => usize::max_value(),
+            QueryKind::Tolerant(word) if cmp::min(levenshtein::typo_budget(word), max_typos) > 0 => {
+                usize::max_value()
+            },
+            QueryKind::Tolerant(word) | QueryKind::Exact(word) => frequency(word),
+            QueryKind::Phrase(words) => words.iter().map(|w| frequency(w)).min().unwrap_or(0),
+        },
+    }
+}
+
+// The phrases are the only operations evaluated within the candidates,
+// the other ones always give all the documents they match.
+fn contains_phrase(operation: &Operation) -> bool {
+    match operation {
+        Operation::And(ops) | Operation::Or(ops) => ops.iter().any(|op| contains_phrase(op)),
+        Operation::AndNot(include, _) => contains_phrase(include),
+        Operation::Query(query) => matches!(query.kind, QueryKind::Phrase(_)),
+    }
+}
+
+fn operation_label(operation: &Operation) -> String {
+    match operation {
+        Operation::And(_) => S("AND"),
+        Operation::Or(_) => S("OR"),
+        Operation::AndNot(..) => S("ANDNOT"),
+        Operation::Query(query) => format!("{:?}", query),
+    }
+}
+
+// Evaluates the tree, the operations already in the evaluation are not evaluated again.
+pub(crate) fn traverse<'o, 'c>(
+    ctx: &'c Context,
+    tree: &'o Operation,
+    max_typos: u8,
+    tracer: &mut Tracer,
+    evaluation: &mut Evaluation<'o, 'c>,
+) -> Cow<'c, Set<DocId>>
+{
+    fn execute_operation<'o, 'c>(
+        ctx: &'c Context,
+        cache: &mut Cache<'o, 'c>,
+        postings: &mut Postings<'o, 'c>,
+        tracer: &mut Tracer,
+        max_typos: u8,
+        operation: &'o Operation,
+        candidates: Option<&DocIds>,
+    ) -> DocIds<'c>
+    {
+        match operation {
+            Operation::And(ops) => execute_and(ctx, cache, postings, tracer, max_typos, ops, candidates),
+            Operation::Or(ops) => execute_or(ctx, cache, postings, tracer, max_typos, ops, candidates),
+            Operation::AndNot(include, exclude) => {
+                execute_and_not(ctx, cache, postings, tracer, max_typos, include, exclude, candidates)
+            },
+            Operation::Query(query) => execute_query(ctx, postings, tracer, max_typos, &query, candidates),
+        }
+    }
+
+    // The operations evaluated within candidates can miss documents outside of them,
+    // they are only cached when they are known to have been completely evaluated.
+    fn evaluate_cached<'o, 'c>(
+        ctx: &'c Context,
+        cache: &mut Cache<'o, 'c>,
+        postings: &mut Postings<'o, 'c>,
+        tracer: &mut Tracer,
+        max_typos: u8,
+        operation: &'o Operation,
+        candidates: Option<&DocIds>,
+    ) -> Option<DocIds<'c>>
+    {
+        if let Some(docids) = cache.get(operation) {
+            trace_cached(tracer, operation, docids);
+            return None;
+        }
+
+        let docids = execute_operation(ctx, cache, postings, tracer, max_typos, operation, candidates);
+        if candidates.is_none() || !contains_phrase(operation) {
+            cache.insert(operation, docids);
+            None
+        } else {
+            Some(docids)
+        }
+    }
+
+    // Records an operation that has not been evaluated again as it was in cache.
+    fn trace_cached(tracer: &mut Tracer, operation: &Operation, docids: &DocIds) {
+        tracer.record_cached(|| Explain {
+            label: operation_label(operation),
+            documents: docids.len(),
+            matches: 0,
+            cache_hit: true,
+            elapsed: Duration::default(),
+            children: Vec::new(),
+        });
+    }
+
+    fn trace_branch(tracer: &mut Tracer, label: &str, docids: &DocIds, before: Option<Instant>) {
+        tracer.exit(|children| Explain {
+            label: label.to_owned(),
+            documents: docids.len(),
+            matches: 0,
+            cache_hit: false,
+            elapsed: before.map(|b| b.elapsed()).unwrap_or_default(),
+            children,
+        });
+    }
+
+    // Evaluates the cheapest operations first, the other ones are evaluated within the documents
+    // that are still candidates and not at all once there are no more candidates.
+    fn execute_and<'o, 'c>(
+        ctx: &'c Context,
+        cache: &mut Cache<'o, 'c>,
+        postings: &mut Postings<'o, 'c>,
+        tracer: &mut Tracer,
+        max_typos: u8,
+        operations: &'o [Rc<Operation>],
+        candidates: Option<&DocIds>,
+    ) -> DocIds<'c>
+    {
+        tracer.enter();
+
+        let before = tracer.start();
+        let mut operations: Vec<_> = operations.iter().map(Rc::as_ref).collect();
+        operations.sort_by_cached_key(|op| estimate(ctx, max_typos, op));
+
+        let mut running: Option<DocIds<'c>> = None;
+
+        for op in operations {
+            if running.as_ref().map_or(false, DocIds::is_empty) { break }
+
+            let within = running.as_ref().or(candidates);
+            let computed = evaluate_cached(ctx, cache, postings, tracer, max_typos, op, within);
+            let docids = match &computed {
+                Some(docids) => docids,
+                None => &cache[op],
+            };
+
+            running = Some(match running {
+                Some(running) => DocIds::intersection(&[&running, docids]),
+                None => docids.clone(),
+            });
+        }
+
+        let docids = running.unwrap_or_default();
+
+        trace_branch(tracer, "AND", &docids, before);
+
+        docids
+    }
+
+    fn execute_or<'o, 'c>(
+        ctx: &'c Context,
+        cache: &mut Cache<'o, 'c>,
+        postings: &mut Postings<'o, 'c>,
+        tracer: &mut Tracer,
+        max_typos: u8,
+        operations: &'o [Rc<Operation>],
+        candidates: Option<&DocIds>,
+    ) -> DocIds<'c>
+    {
+        tracer.enter();
+
+        let before = tracer.start();
+        let mut computed = Vec::new();
+
+        for op in operations.iter().map(Rc::as_ref) {
+            computed.extend(evaluate_cached(ctx, cache, postings, tracer, max_typos, op, candidates));
+        }
+
+        let mut results: Vec<_> = operations.iter().filter_map(|op| cache.get(op.as_ref())).collect();
+        results.extend(&computed);
+        let docids = DocIds::union(&results);
+
+        trace_branch(tracer, "OR", &docids, before);
+
+        docids
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn execute_and_not<'o, 'c>(
+        ctx: &'c Context,
+        cache: &mut Cache<'o, 'c>,
+        postings: &mut Postings<'o, 'c>,
+        tracer: &mut Tracer,
+        max_typos: u8,
+        include: &'o Operation,
+        exclude: &'o Operation,
+        candidates: Option<&DocIds>,
+    ) -> DocIds<'c>
+    {
+        tracer.enter();
+
+        let before = tracer.start();
+
+        let included = match cache.get(include) {
+            Some(docids) => {
+                trace_cached(tracer, include, docids);
+                docids.clone()
+            },
+            None => execute_operation(ctx, cache, postings, tracer, max_typos, include, candidates),
+        };
+
+        // The excluded matches must not be highlighted, we evaluate
+        // them with their own postings and cache for this reason.
+        let mut excluded_cache = Cache::new();
+        let mut excluded_postings = Postings::new();
+        let excluded = execute_operation(
+            ctx,
+            &mut excluded_cache,
+            &mut excluded_postings,
+            tracer,
+            max_typos,
+            exclude,
+            None,
+        );
+
+        let docids = DocIds::difference(&included, &excluded);
+
+        trace_branch(tracer, "ANDNOT", &docids, before);
+
+        docids
+    }
+
+    fn execute_query<'o, 'c>(
+        ctx: &'c Context,
+        postings: &mut Postings<'o, 'c>,
+        tracer: &mut Tracer,
+        max_typos: u8,
+        query: &'o Query,
+        candidates: Option<&DocIds>,
+    ) -> DocIds<'c>
+    {
+        let before = tracer.start();
+
+        let Query { prefix, field, kind, .. } = query;
+
+        // The queries in an unknown field match nothing.
+        let attribute = field.as_ref().map(|name| ctx.attributes.iter().position(|a| a == name));
+        let in_field = |position: Position| {
+            attribute.map_or(true, |a| a == Some(attribute_of(position) as usize))
+        };
+
+        let (docids, derivations) = match kind {
+            QueryKind::Tolerant(_) | QueryKind::Exact(_) => {
+                let derived = derive_words(ctx, max_typos, query);
+                match field {
+                    // The words are restricted to the field before the expansions are limited,
+                    // the words only found in the other fields must not take the place of the others.
+                    Some(_) => union_restricted(limit_expansions(ctx, *prefix, restrict_to_field(derived, in_field))),
+                    None => union_derived(limit_expansions(ctx, *prefix, derived)),
+                }
+            },
+            QueryKind::Phrase(words) => {
+                let default = SetBuf::default();
+                let lists: Vec<_> = words.iter().map(|word| {
+                    ctx.postings_list(word).map_or(default.as_set(), |pl| pl.matches)
+                }).collect();
+
+                // A phrase is expensive, it is only searched in the candidates.
+                let candidates = candidates.map(DocIds::as_set);
+                let matches = phrase_matches(&lists, candidates.as_deref(), in_field);
+
+                let mut docids: Vec<_> = matches.iter().map(|m| m.docid).collect();
+                docids.dedup();
+
+                let matches = Cow::Owned(matches);
+                let derivation = Derivation { word: words.join(" "), typos: 0, matches };
+
+                (DocIds::Set(Cow::Owned(SetBuf::new(docids).unwrap())), vec![derivation])
+            },
+        };
+
+        // The phrases and the fields give sets that are compressed when the context uses the bitmaps.
+        let docids = docids.into_backend(ctx.backend());
+
+        tracer.record(|| Explain {
+            label: format!("{:?}", query),
+            documents: docids.len(),
+            matches: derivations.iter().map(|d| d.matches.len()).sum(),
+            cache_hit: false,
+            elapsed: before.map(|b| b.elapsed()).unwrap_or_default(),
+            children: Vec::new(),
+        });
+
+        // A phrase can be evaluated again within other candidates.
+        match postings.entry(query) {
+            Entry::Occupied(mut entry) => merge_derivations(entry.get_mut(), derivations),
+            Entry::Vacant(entry) => { entry.insert(derivations); },
+        }
+        docids
+    }
+
+    // Returns the matches of the words following each other in the documents,
+    // the positions of the first word are the candidate starts of the phrase.
+    fn phrase_matches<F>(
+        lists: &[&Set<DocIndex>],
+        candidates: Option<&Set<DocId>>,
+        in_field: F,
+    ) -> SetBuf<DocIndex>
+    where F: Fn(Position) -> bool,
+    {
+        let (first, others) = match lists.split_first() {
+            Some(split) => split,
+            None => return SetBuf::default(),
+        };
+
+        let mut starts: Vec<_> = first.iter()
+            .filter(|m| candidates.map_or(true, |c| c.binary_search(&m.docid).is_ok()))
+            .filter(|m| in_field(m.position))
+            .copied()
+            .collect();
+        for (i, list) in others.iter().enumerate() {
+            let offset = i as u64 + 1;
+            let iter = merge_join_by(starts.iter(), list.as_slice(), |a, b| {
+                (a.docid, u64::from(a.position) + offset).cmp(&(b.docid, u64::from(b.position)))
+            });
+
+            // A phrase can't start in an attribute and end in the next one.
+            starts = iter.filter_map(EitherOrBoth::both)
+                .filter(|(a, b)| attribute_of(a.position) == attribute_of(b.position))
+                .map(|(a, _)| *a)
+                .collect();
+            if starts.is_empty() { break }
+        }
+
+        // The phrase can overlap itself (e.g. "a a" in "a a a"),
+        // that is why we must dedup the positions.
+        let matches = starts.iter().flat_map(|DocIndex { docid, position }| {
+            (0..lists.len()).map(move |i| DocIndex { docid: *docid, position: position + i as Position })
+        });
+
+        SetBuf::from_dirty(matches.collect())
+    }
+
+    fn merge_derivations<'c>(derivations: &mut Vec<Derivation<'c>>, others: Vec<Derivation<'c>>) {
+        for other in others {
+            match derivations.iter_mut().find(|d| d.word == other.word && d.typos == other.typos) {
+                Some(derivation) => {
+                    let union = sdset::duo::Union::new(&derivation.matches, &other.matches);
+                    derivation.matches = Cow::Owned(union.into_set_buf());
+                },
+                None => derivations.push(other),
+            }
+        }
+    }
+
+    // Returns the words of the dictionary derived from a word query, with their number of typos.
+    fn derive_words<'c>(ctx: &'c Context, max_typos: u8, query: &Query) -> Vec<(u8, String, PostingsRef<'c>)> {
+        match &query.kind {
+            QueryKind::Tolerant(word) => {
+                let max_typos = cmp::min(levenshtein::typo_budget(word), max_typos);
+                let distance = if query.prefix { levenshtein::prefix_distance } else { levenshtein::distance };
+
+                let words = match Levenshtein::new(word, max_typos as u32) {
+                    Ok(dfa) if query.prefix => ctx.search_words(dfa.starts_with()),
+                    Ok(dfa) => ctx.search_words(dfa),
+                    // The automaton is too big, we only look for the word itself.
+                    Err(_) => ctx.postings_list(word).map(|pl| (word.clone(), pl)).into_iter().collect(),
+                };
+
+                words.into_iter().filter_map(|(w, pl)| {
+                    distance(word, &w, max_typos).map(|typos| (typos, w, pl))
+                })
+                .collect()
+            },
+            QueryKind::Exact(word) if query.prefix => {
+                let words = ctx.search_words(Str::new(word).starts_with());
+                words.into_iter().map(|(w, pl)| (0, w, pl)).collect()
+            },
+            QueryKind::Exact(word) => {
+                ctx.postings_list(word).map(|pl| (0, word.clone(), pl)).into_iter().collect()
+            },
+            // The phrases are not derived, their words must be found as they are.
+            QueryKind::Phrase(_) => Vec::new(),
+        }
+    }
+
+    // Keeps the matches of the derived words that are in the field,
+    // the words that are not found in the field are removed.
+    fn restrict_to_field<F>(
+        derived: Vec<(u8, String, PostingsRef<'_>)>,
+        in_field: F,
+    ) -> Vec<(u8, String, SetBuf<DocIndex>)>
+    where F: Fn(Position) -> bool,
+    {
+        derived.into_iter().filter_map(|(typos, word, postings)| {
+            let matches: Vec<_> = postings.matches.iter().filter(|m| in_field(m.position)).copied().collect();
+            if matches.is_empty() { None } else { Some((typos, word, SetBuf::new_unchecked(matches))) }
+        })
+        .collect()
+    }
+
+    // Sorts the derived words by number of typos and keeps
+    // the best ones if the prefix expansions are limited.
+    fn limit_expansions<T>(ctx: &Context, prefix: bool, mut derived: Vec<(u8, String, T)>) -> Vec<(u8, String, T)> {
+        derived.sort_unstable_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        if let (true, Some(max)) = (prefix, ctx.max_prefix_expansions) {
+            derived.truncate(max);
+        }
+        derived
+    }
+
+    // Unions the matches of the words restricted to a field, the docids are computed from the matches.
+    fn union_restricted<'c>(restricted: Vec<(u8, String, SetBuf<DocIndex>)>) -> (DocIds<'c>, Vec<Derivation<'c>>) {
+        let mut docids = Vec::new();
+        let mut derivations = Vec::new();
+
+        for (typos, word, matches) in restricted {
+            docids.extend(matches.iter().map(|m| m.docid));
+            derivations.push(Derivation { word, typos, matches: Cow::Owned(matches) });
+        }
+
+        (DocIds::Set(Cow::Owned(SetBuf::from_dirty(docids))), derivations)
+    }
+
+    // Unions the postings lists of the dictionary words derived from a query,
+    // the docids are only borrowed when a single word has been derived.
+    fn union_derived<'c, I>(derived: I) -> (DocIds<'c>, Vec<Derivation<'c>>)
+    where I: IntoIterator<Item=(u8, String, PostingsRef<'c>)>,
+    {
+        let mut docids = Vec::new();
+        let mut derivations = Vec::new();
+
+        for (typos, word, PostingsRef { docids: ids, matches, bitmap }) in derived {
+            let ids = match bitmap {
+                Some(bitmap) => DocIds::Bitmap(Cow::Borrowed(bitmap)),
+                None => DocIds::Set(Cow::Borrowed(ids)),
+            };
+            docids.push(ids);
+            derivations.push(Derivation { word, typos, matches: Cow::Borrowed(matches) });
+        }
+
+        let docids = match docids.len() {
+            0 => DocIds::default(),
+            1 => docids.pop().unwrap(),
+            _ => DocIds::union(&docids.iter().collect::<Vec<_>>()),
+        };
+
+        (docids, derivations)
+    }
+
+    let Evaluation { cache, postings } = evaluation;
+    let docids = execute_operation(ctx, cache, postings, tracer, max_typos, tree, None);
+
+    docids.into_set()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use big_s::S;
+    use crate::fixtures::{context, document, search, titles};
+
+    #[test]
+    fn long_documents() {
+        let text = format!("{} hello world", "word ".repeat(1000));
+        let ctx = context(&[document(70_000, &[("body", &text)])]);
+
+        let world = ctx.postings_list("world").unwrap();
+        assert_eq!(world.matches.as_slice(), &[DocIndex { docid: 70_000, position: 1001 }]);
+        assert_eq!(search(&ctx, "\"hello world\""), vec![70_000]);
+    }
+
+    #[test]
+    fn phrases_in_attributes() {
+        // The last word of the title is right before the first one of the body.
+        let title = format!("{}hello", "word ".repeat(WordIndex::max_value() as usize));
+
+        let ctx = context(&[
+            document(0, &[("title", &title), ("body", "world")]),
+            document(1, &[("title", "hello"), ("body", "hello world")]),
+        ]);
+
+        assert_eq!(search(&ctx, "\"hello world\""), vec![1]);
+    }
+
+    #[test]
+    fn fielded_queries() {
+        let ctx = context(&[
+            document(0, &[("Title", "hello world"), ("body", "big")]),
+            document(1, &[("Title", "big"), ("body", "hello world")]),
+            document(2, &[("Title", "world hello"), ("body", "hello 12:30")]),
+        ]);
+
+        assert_eq!(search(&ctx, "hello"), vec![0, 1, 2]);
+        assert_eq!(search(&ctx, "title:hello"), vec![0, 2]);
+        assert_eq!(search(&ctx, "TITLE:hello"), vec![0, 2]);
+        assert_eq!(search(&ctx, "body:hello"), vec![1, 2]);
+        assert_eq!(search(&ctx, "title:\"hello world\""), vec![0]);
+        assert_eq!(search(&ctx, "body:\"hello world\""), vec![1]);
+        assert_eq!(search(&ctx, "hello -title:big"), vec![0, 2]);
+
+        // The words whose prefix is not a field are searched as they are.
+        assert_eq!(search(&ctx, "12:30"), vec![2]);
+        assert_eq!(search(&ctx, "tags:hello"), Vec::<DocId>::new());
+
+        let (tree, _) = create_query_tree(&ctx, "title:hello");
+        assert!(format!("{:?}", tree).contains("field: \"title\""));
+    }
+
+    #[test]
+    fn fielded_prefix_expansions() {
+        let mut ctx = context(&[
+            document(0, &[("title", "help"), ("body", "hello")]),
+            document(1, &[("title", "word"), ("body", "help")]),
+        ]);
+        ctx.max_prefix_expansions = Some(1);
+
+        // The words that are not in the field don't take the place of the ones that are.
+        assert_eq!(search(&ctx, "title:hel"), vec![0]);
+        assert_eq!(search(&ctx, "body:hel"), vec![0]);
+    }
+
+    #[test]
+    fn optional_stop_words() {
+        let mut ctx = titles(&["this is the world", "hello world", "the hello", "this"]);
+        ctx.stop_words = vec![S("this"), S("is"), S("the")].into_iter().collect();
+
+        assert_eq!(search(&ctx, "the world"), vec![0, 1]);
+        assert_eq!(search(&ctx, "hello the"), vec![1, 2]);
+        assert_eq!(search(&ctx, "this is hello"), vec![1, 2]);
+        assert_eq!(search(&ctx, "this is"), vec![0]);
+        assert_eq!(search(&ctx, "this"), vec![0, 3]);
+        assert_eq!(search(&ctx, "\"the world\""), vec![0]);
+
+        // The stop words are still searched to rank the documents containing them.
+        let (tree, _) = create_query_tree(&ctx, "the world");
+        let result = traverse_query_tree(&ctx, &tree);
+        assert!(result.queries.keys().any(|q| format!("{:?}", q).contains("\"the\"")));
+    }
+
+    #[test]
+    fn concatenation_options() {
+        let ctx = titles(&["newyork city", "new york cityscape"]);
+        let create = |query: &str, options: &QueryTreeOptions| {
+            let (tree, _) = create_query_tree_with_options(&ctx, query, options);
+            let docids = traverse_query_tree(&ctx, &tree).docids.to_vec();
+            (format!("{:?}", tree), docids)
+        };
+
+        let (tree, docids) = create("new york city", &QueryTreeOptions::default());
+        assert!(tree.contains("\"newyorkcity\"") && tree.contains("PrefixExact { id: 3, word: \"yorkcity\" }"));
+        assert_eq!(docids, vec![0, 1]);
+
+        let options = QueryTreeOptions { concatenations: false, ..QueryTreeOptions::default() };
+        let (tree, docids) = create("new york city", &options);
+        assert!(!tree.contains("newyork"));
+        assert_eq!(docids, vec![1]);
+
+        let options = QueryTreeOptions { max_ngram: 2, prefix_concatenations: false, ..QueryTreeOptions::default() };
+        let (tree, _) = create("new york city", &options);
+        assert!(!tree.contains("newyorkcity") && tree.contains("Exact { id: 3, word: \"yorkcity\" }"));
+
+        let options = QueryTreeOptions { concatenations_in_dictionary: true, ..QueryTreeOptions::default() };
+        let (tree, docids) = create("new york city", &options);
+        assert!(tree.contains("\"newyork\"") && !tree.contains("yorkcity"));
+        assert_eq!(docids, vec![0, 1]);
+    }
+
+    #[test]
+    fn pruned_absent_words() {
+        let mut ctx = titles(&["hello earth", "helloworld"]);
+        ctx.synonyms.insert(vec![S("world")], vec![vec![S("earth")], vec![S("nature")]]);
+        ctx.synonyms.insert(vec![S("hello"), S("world")], vec![vec![S("bonjour"), S("le"), S("monde")]]);
+
+        let pruned = QueryTreeOptions { prune_absent_words: true, ..QueryTreeOptions::default() };
+
+        let (tree, mapping) = create_query_tree(&ctx, "hello world");
+        let tree = format!("{:?}", tree);
+        assert!(tree.contains("nature") && tree.contains("bonjour"));
+        assert_eq!(mapping[&1], 1..3);
+
+        let (tree, mapping) = create_query_tree_with_options(&ctx, "hello world", &pruned);
+        let result = traverse_query_tree(&ctx, &tree);
+        let tree = format!("{:?}", tree);
+        assert!(tree.contains("earth") && tree.contains("helloworld"));
+        assert!(!tree.contains("nature") && !tree.contains("bonjour"));
+        assert_eq!(mapping[&1], 1..2);
+        assert_eq!(result.docids.as_slice(), &[0, 1]);
+
+        // A phrase absent from the dictionary can't match, neither can the rest of the query.
+        let (tree, mapping) = create_query_tree_with_options(&ctx, "\"big world\" hello", &pruned);
+        assert!(traverse_query_tree(&ctx, &tree).docids.is_empty());
+        assert!(!format!("{:?}", tree).contains("hello"));
+        assert!(mapping.keys().all(|&id| id < 3));
+    }
+
+    #[test]
+    fn and_evaluation_order() {
+        let ctx = titles(&["hello world", "hello big world", "hello", "world hello big"]);
+        let evaluate = |tree: &Operation| {
+            let (result, explain) = explain_query_tree(&ctx, tree);
+            let labels: Vec<_> = explain.children.iter().map(|e| e.label.clone()).collect();
+            (result.docids.to_vec(), labels)
+        };
+
+        // The rarest words are evaluated first.
+        let tree = Operation::and(vec![Operation::exact(0, false, "hello"), Operation::exact(1, false, "big")]);
+        let (docids, labels) = evaluate(&tree);
+        assert_eq!(docids, vec![1, 3]);
+        assert!(labels[0].contains("big") && labels[1].contains("hello"));
+
+        // A word allowed to have typos can be corrected into frequent words, even when it is absent.
+        let tree = Operation::and(vec![Operation::tolerant(0, false, "hellp"), Operation::exact(1, false, "big")]);
+        let (docids, labels) = evaluate(&tree);
+        assert_eq!(docids, vec![1, 3]);
+        assert!(labels[0].contains("big") && labels[1].contains("hellp"));
+
+        // The other words are not evaluated once there are no more candidates.
+        let tree = Operation::and(vec![Operation::exact(0, false, "hello"), Operation::exact(1, false, "zzz")]);
+        let (docids, labels) = evaluate(&tree);
+        assert!(docids.is_empty());
+        assert_eq!(labels.len(), 1);
+
+        // The phrase is searched within the candidates of the first branch, this result must not
+        // be reused by the second branch where the phrase is evaluated first, everywhere.
+        let phrase = || Operation::phrase(2, false, &[S("hello"), S("world")]);
+        let tree = Operation::or(vec![
+            Operation::and(vec![Operation::exact(1, false, "big"), phrase()]),
+            Operation::and(vec![Operation::exact(0, false, "hello"), phrase()]),
+        ]);
+
+        let result = traverse_query_tree(&ctx, &tree);
+        assert_eq!(result.docids.as_slice(), &[0]);
+
+        let phrase = result.queries.iter().find(|(q, _)| q.id == 2).map(|(_, d)| &d[0]).unwrap();
+        let matches: Vec<_> = phrase.matches.iter().map(|m| (m.docid, m.position)).collect();
+        assert_eq!(matches, vec![(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn repeated_words() {
+        let ctx = titles(&["hello world"]);
+
+        for &optimize in &[false, true] {
+            let options = QueryTreeOptions { optimize, ..QueryTreeOptions::default() };
+            let (tree, mapping) = create_query_tree_with_options(&ctx, "hello hello world", &options);
+            let tree = format!("{:?}", tree);
+
+            // The second word is searched on its own, it is mapped to its place in the query.
+            assert!(tree.contains("Tolerant { id: 0, word: \"hello\" }"));
+            assert!(tree.contains("Tolerant { id: 1, word: \"hello\" }"));
+            assert_eq!(mapping[&1], 1..2);
+        }
+    }
+}
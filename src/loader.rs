@@ -0,0 +1,218 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::error::Error;
+use crate::indexer::{Document, Indexer};
+use crate::query_parser::normalize;
+use crate::synonyms::Synonyms;
+use crate::{Context, DocId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    JsonLines,
+    Csv,
+}
+
+impl Format {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Format> {
+        match path.as_ref().extension()?.to_str()? {
+            "csv" => Some(Format::Csv),
+            "json" => Some(Format::Json),
+            "jsonl" | "ndjson" => Some(Format::JsonLines),
+            _ => None,
+        }
+    }
+}
+
+// Keeps the searchable fields in the order they are declared, all the fields are searchable
+// if none are declared. The names are normalized like the indexer does with the attributes.
+fn searchable_fields(mut fields: Vec<(String, String)>, searchable: &[String]) -> Vec<(String, String)> {
+    if searchable.is_empty() { return fields }
+
+    searchable.iter().filter_map(|name| {
+        let name = normalize(name);
+        let i = fields.iter().position(|(n, _)| normalize(n) == name)?;
+        Some(fields.swap_remove(i))
+    })
+    .collect()
+}
+
+fn next_docid(count: usize) -> Result<DocId, Error> {
+    if count > DocId::max_value() as usize { return Err(Error::TooManyDocuments) }
+    Ok(count as DocId)
+}
+
+// The strings, numbers and booleans of the object are indexed, the other values are ignored.
+fn json_document(id: DocId, value: Value, searchable: &[String]) -> Option<Document> {
+    let object = match value {
+        Value::Object(object) => object,
+        _ => return None,
+    };
+
+    let fields = object.into_iter().filter_map(|(name, value)| match value {
+        Value::String(text) => Some((name, text)),
+        Value::Number(_) | Value::Bool(_) => Some((name, value.to_string())),
+        _ => None,
+    });
+
+    let fields = searchable_fields(fields.collect(), searchable);
+    Some(Document { id, fields })
+}
+
+// Reads one JSON object per line.
+pub fn read_json_lines<R: Read>(reader: R, searchable: &[String]) -> Result<Vec<Document>, Error> {
+    let mut documents = Vec::new();
+
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() { continue }
+
+        let id = next_docid(documents.len())?;
+        let value = serde_json::from_str(&line)?;
+        let document = json_document(id, value, searchable).ok_or(Error::NotAnObject(i + 1))?;
+        documents.push(document);
+    }
+
+    Ok(documents)
+}
+
+// Reads a JSON array of objects.
+pub fn read_json<R: Read>(reader: R, searchable: &[String]) -> Result<Vec<Document>, Error> {
+    let values = match serde_json::from_reader(BufReader::new(reader))? {
+        Value::Array(values) => values,
+        _ => return Err(Error::NotAnArray),
+    };
+
+    let mut documents = Vec::with_capacity(values.len());
+    for (i, value) in values.into_iter().enumerate() {
+        let id = next_docid(documents.len())?;
+        let document = json_document(id, value, searchable).ok_or(Error::NotAnObject(i + 1))?;
+        documents.push(document);
+    }
+
+    Ok(documents)
+}
+
+// Reads a CSV file with headers, the headers are the names of the fields.
+pub fn read_csv<R: Read>(reader: R, searchable: &[String]) -> Result<Vec<Document>, Error> {
+    let mut reader = csv::Reader::from_reader(reader);
+    let headers = reader.headers()?.clone();
+    let mut documents = Vec::new();
+
+    for record in reader.records() {
+        let record = record?;
+        let fields = headers.iter().zip(record.iter()).map(|(n, t)| (n.to_owned(), t.to_owned()));
+
+        let id = next_docid(documents.len())?;
+        let fields = searchable_fields(fields.collect(), searchable);
+        documents.push(Document { id, fields });
+    }
+
+    Ok(documents)
+}
+
+// Reads the documents of a file, the format is deduced from the extension
+// and the documents are given ids in the order they appear in the file.
+pub fn read_documents<P: AsRef<Path>>(path: P, searchable: &[String]) -> Result<Vec<Document>, Error> {
+    let format = Format::from_path(&path).ok_or(Error::UnknownFormat)?;
+    let file = File::open(path)?;
+
+    match format {
+        Format::Json => read_json(file, searchable),
+        Format::JsonLines => read_json_lines(file, searchable),
+        Format::Csv => read_csv(file, searchable),
+    }
+}
+
+pub fn load_context<P: AsRef<Path>>(
+    path: P,
+    searchable: &[String],
+    synonyms: Synonyms,
+) -> Result<Context, Error>
+{
+    let mut indexer = Indexer::with_attributes(searchable);
+    for document in read_documents(path, searchable)? {
+        indexer.index_document(&document);
+    }
+
+    let attributes = indexer.attributes().to_vec();
+    let mut context = Context::new(synonyms, indexer.build());
+    context.attributes = attributes;
+
+    Ok(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use big_s::S;
+
+    #[test]
+    fn json_lines() {
+        let text = r#"
+            {"title": "Hello World", "year": 2020, "tags": ["a"], "body": "big"}
+
+            {"body": "hello", "title": "there"}
+        "#;
+
+        let documents = read_json_lines(text.as_bytes(), &[S("title"), S("body")]).unwrap();
+        assert_eq!(documents, vec![
+            Document { id: 0, fields: vec![(S("title"), S("Hello World")), (S("body"), S("big"))] },
+            Document { id: 1, fields: vec![(S("title"), S("there")), (S("body"), S("hello"))] },
+        ]);
+
+        // The fields are kept in the order of the document.
+        let documents = read_json_lines(text.as_bytes(), &[]).unwrap();
+        assert_eq!(documents[0].fields, vec![
+            (S("title"), S("Hello World")),
+            (S("year"), S("2020")),
+            (S("body"), S("big")),
+        ]);
+
+        let documents = read_json_lines(text.as_bytes(), &[S("Title")]).unwrap();
+        assert_eq!(documents[1].fields, vec![(S("title"), S("there"))]);
+
+        let error = read_json_lines("[1, 2]".as_bytes(), &[]).unwrap_err();
+        assert!(matches!(error, Error::NotAnObject(1)));
+    }
+
+    #[test]
+    fn json_array() {
+        let text = r#"[{"title": "Hello World", "tags": ["a"]}, {"title": "there"}]"#;
+
+        let documents = read_json(text.as_bytes(), &[]).unwrap();
+        assert_eq!(documents, vec![
+            Document { id: 0, fields: vec![(S("title"), S("Hello World"))] },
+            Document { id: 1, fields: vec![(S("title"), S("there"))] },
+        ]);
+
+        let error = read_json(r#"[{"title": "hello"}, 2]"#.as_bytes(), &[]).unwrap_err();
+        assert!(matches!(error, Error::NotAnObject(2)));
+
+        let error = read_json(r#"{"title": "hello"}"#.as_bytes(), &[]).unwrap_err();
+        assert!(matches!(error, Error::NotAnArray));
+    }
+
+    #[test]
+    fn csv_records() {
+        let text = "id,title,body\n12,\"Hello, World\",big\n13,there,hello\n";
+
+        let documents = read_csv(text.as_bytes(), &[S("body"), S("title")]).unwrap();
+        assert_eq!(documents, vec![
+            Document { id: 0, fields: vec![(S("body"), S("big")), (S("title"), S("Hello, World"))] },
+            Document { id: 1, fields: vec![(S("body"), S("hello")), (S("title"), S("there"))] },
+        ]);
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Format::from_path("movies.csv"), Some(Format::Csv));
+        assert_eq!(Format::from_path("movies.jsonl"), Some(Format::JsonLines));
+        assert_eq!(Format::from_path("movies.json"), Some(Format::Json));
+        assert_eq!(Format::from_path("movies.txt"), None);
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::mem;
+use std::rc::Rc;
+
+use crate::Operation;
+
+// Simplifies a query tree, the documents it matches and the queries it evaluates stay the same:
+// the nested operations of the same kind are flattened, the branches that can't match any
+// document are removed, the factors common to the alternatives of an `Or` are hoisted
+// and the identical subtrees are shared, the tree becomes a DAG.
+pub fn optimize(tree: &Operation) -> Operation {
+    let tree = simplify(tree).unwrap_or_else(|| Operation::Or(Vec::new()));
+    let root = share(tree, &mut HashMap::new());
+    Rc::try_unwrap(root).unwrap_or_else(|root| (*root).clone())
+}
+
+// The operations are compared with the ids of their queries, the equality of the operations
+// ignores them but the queries of different query words must all stay in the tree.
+fn same(a: &Operation, b: &Operation) -> bool {
+    match (a, b) {
+        (Operation::And(a), Operation::And(b)) | (Operation::Or(a), Operation::Or(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        },
+        (Operation::AndNot(ai, ae), Operation::AndNot(bi, be)) => same(ai, bi) && same(ae, be),
+        (Operation::Query(a), Operation::Query(b)) => a.id == b.id && a == b,
+        _ => false,
+    }
+}
+
+fn hash_with_ids<H: Hasher>(operation: &Operation, state: &mut H) {
+    mem::discriminant(operation).hash(state);
+    match operation {
+        Operation::And(ops) | Operation::Or(ops) => ops.iter().for_each(|op| hash_with_ids(op, state)),
+        Operation::AndNot(include, exclude) => {
+            hash_with_ids(include, state);
+            hash_with_ids(exclude, state);
+        },
+        Operation::Query(query) => {
+            query.id.hash(state);
+            query.hash(state);
+        },
+    }
+}
+
+// An operation hashed and compared with the ids of its queries.
+struct WithIds(Operation);
+
+impl PartialEq for WithIds {
+    fn eq(&self, other: &Self) -> bool {
+        same(&self.0, &other.0)
+    }
+}
+
+impl Eq for WithIds {}
+
+impl Hash for WithIds {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_with_ids(&self.0, state)
+    }
+}
+
+// Returns `None` when the operation can't match any document,
+// an empty `And` or `Or` never matches.
+fn simplify(operation: &Operation) -> Option<Operation> {
+    match operation {
+        Operation::And(ops) => {
+            let ops: Option<Vec<_>> = ops.iter().map(|op| simplify(op)).collect();
+            make_and(ops?)
+        },
+        Operation::Or(ops) => make_or(ops.iter().filter_map(|op| simplify(op)).collect()),
+        Operation::AndNot(include, exclude) => {
+            let include = simplify(include)?;
+            match simplify(exclude) {
+                Some(exclude) => Some(Operation::and_not(include, exclude)),
+                None => Some(include),
+            }
+        },
+        Operation::Query(_) => Some(operation.clone()),
+    }
+}
+
+fn children(operation: Operation, and: bool) -> Vec<Operation> {
+    match operation {
+        Operation::And(ops) if and => ops.into_iter().map(unwrap_or_clone).collect(),
+        Operation::Or(ops) if !and => ops.into_iter().map(unwrap_or_clone).collect(),
+        operation => vec![operation],
+    }
+}
+
+// Flattens the nested operations of the same kind and removes the duplicates.
+fn flatten(ops: Vec<Operation>, and: bool) -> Vec<Operation> {
+    let mut flattened = Vec::with_capacity(ops.len());
+    for op in ops.into_iter().flat_map(|op| children(op, and)) {
+        if !flattened.iter().any(|f| same(f, &op)) {
+            flattened.push(op);
+        }
+    }
+    flattened
+}
+
+fn make_and(ops: Vec<Operation>) -> Option<Operation> {
+    let mut ops = flatten(ops, true);
+    match ops.len() {
+        0 => None,
+        1 => ops.pop(),
+        _ => Some(Operation::and(ops)),
+    }
+}
+
+fn make_or(ops: Vec<Operation>) -> Option<Operation> {
+    let mut ops = hoist_factors(flatten(ops, false));
+    match ops.len() {
+        0 => None,
+        1 => ops.pop(),
+        _ => Some(Operation::or(ops)),
+    }
+}
+
+// Rewrites `(a AND b) OR (a AND c)` into `a AND (b OR c)`, the `And`s that only
+// contain the factor are left untouched for their queries to still be evaluated.
+fn hoist_factors(ops: Vec<Operation>) -> Vec<Operation> {
+    let mut alternatives: Vec<_> = ops.into_iter().map(|op| children(op, true)).collect();
+
+    while let Some(factor) = most_shared_factor(&alternatives) {
+        let contains = |factors: &[Operation]| factors.len() > 1 && factors.iter().any(|f| same(f, &factor));
+        let first = alternatives.iter().position(|a| contains(a)).unwrap();
+        let mut remainders = Vec::new();
+        let mut i = first;
+        while i < alternatives.len() {
+            if contains(&alternatives[i]) {
+                let mut factors = alternatives.remove(i);
+                factors.retain(|f| !same(f, &factor));
+                remainders.push(make_and(factors).expect("the remainder is not empty"));
+            } else {
+                i += 1;
+            }
+        }
+
+        let remainders = make_or(remainders).expect("the remainders are not empty");
+        let hoisted = make_and(vec![factor, remainders]).expect("the factor is not empty");
+        alternatives.insert(first, vec![hoisted]);
+    }
+
+    alternatives.into_iter().filter_map(make_and).collect()
+}
+
+// Returns the factor found in the most alternatives, if it is found in at least two.
+fn most_shared_factor(alternatives: &[Vec<Operation>]) -> Option<Operation> {
+    let mut best: Option<(&Operation, usize)> = None;
+
+    for factor in alternatives.iter().filter(|a| a.len() > 1).flatten() {
+        let count = alternatives.iter().filter(|a| a.len() > 1 && a.iter().any(|f| same(f, factor))).count();
+        if count > 1 && best.map_or(true, |(_, c)| count > c) {
+            best = Some((factor, count));
+        }
+    }
+
+    best.map(|(factor, _)| factor.clone())
+}
+
+// Interns the operations bottom up for the identical subtrees to be the same node.
+fn share(operation: Operation, interned: &mut HashMap<WithIds, Rc<Operation>>) -> Rc<Operation> {
+    let mut share_all = |ops: Vec<Rc<Operation>>| -> Vec<_> {
+        ops.into_iter().map(|op| share(unwrap_or_clone(op), interned)).collect()
+    };
+
+    let operation = match operation {
+        Operation::And(ops) => Operation::And(share_all(ops)),
+        Operation::Or(ops) => Operation::Or(share_all(ops)),
+        Operation::AndNot(include, exclude) => {
+            let include = share(unwrap_or_clone(include), interned);
+            let exclude = share(unwrap_or_clone(exclude), interned);
+            Operation::AndNot(include, exclude)
+        },
+        operation @ Operation::Query(_) => operation,
+    };
+
+    interned.entry(WithIds(operation.clone())).or_insert_with(|| Rc::new(operation)).clone()
+}
+
+fn unwrap_or_clone(operation: Rc<Operation>) -> Operation {
+    Rc::try_unwrap(operation).unwrap_or_else(|op| (*op).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(id: usize) -> Operation {
+        Operation::exact(id, false, &id.to_string())
+    }
+
+    fn nodes(operation: &Operation, seen: &mut Vec<*const Operation>) {
+        let ops: Vec<&Rc<Operation>> = match operation {
+            Operation::And(ops) | Operation::Or(ops) => ops.iter().collect(),
+            Operation::AndNot(include, exclude) => vec![include, exclude],
+            Operation::Query(_) => Vec::new(),
+        };
+
+        for op in ops {
+            let ptr = &**op as *const Operation;
+            if !seen.contains(&ptr) {
+                seen.push(ptr);
+                nodes(op, seen);
+            }
+        }
+    }
+
+    #[test]
+    fn flatten_and_remove_empty() {
+        let tree = Operation::or(vec![
+            Operation::and(vec![query(0), Operation::and(vec![query(1), query(0)])]),
+            Operation::and(vec![query(2), Operation::or(Vec::new())]),
+            Operation::or(vec![query(3), Operation::or(vec![query(3)])]),
+        ]);
+
+        let expected = Operation::or(vec![Operation::and(vec![query(0), query(1)]), query(3)]);
+        assert_eq!(optimize(&tree), expected);
+
+        let tree = Operation::and_not(query(0), Operation::and(Vec::new()));
+        assert_eq!(optimize(&tree), query(0));
+        assert_eq!(optimize(&Operation::and(vec![Operation::or(Vec::new())])), Operation::or(Vec::new()));
+    }
+
+    #[test]
+    fn hoist_common_factors() {
+        let tree = Operation::or(vec![
+            Operation::and(vec![query(0), query(1)]),
+            Operation::and(vec![query(2), query(1)]),
+            query(3),
+        ]);
+
+        let expected = Operation::or(vec![
+            Operation::and(vec![query(1), Operation::or(vec![query(0), query(2)])]),
+            query(3),
+        ]);
+        assert_eq!(optimize(&tree), expected);
+
+        // The queries of the second alternative must still be evaluated.
+        let tree = Operation::or(vec![query(1), Operation::and(vec![query(0), query(1)])]);
+        assert_eq!(optimize(&tree), tree);
+    }
+
+    #[test]
+    fn shared_subtrees() {
+        let tail = || Operation::or(vec![query(2), query(3)]);
+        let tree = Operation::or(vec![
+            Operation::and(vec![query(0), tail()]),
+            Operation::and_not(query(1), tail()),
+        ]);
+
+        let optimized = optimize(&tree);
+        assert_eq!(optimized, tree);
+
+        let (mut before, mut after) = (Vec::new(), Vec::new());
+        nodes(&tree, &mut before);
+        nodes(&optimized, &mut after);
+        assert_eq!((before.len(), after.len()), (10, 7));
+    }
+
+    #[test]
+    fn repeated_words() {
+        let hello = |id| Operation::exact(id, false, "hello");
+        let tree = Operation::or(vec![
+            Operation::and(vec![hello(0), hello(1)]),
+            Operation::and(vec![hello(1), hello(2)]),
+        ]);
+
+        // The equality of the operations ignores the ids, their debug output doesn't.
+        let expected = Operation::and(vec![hello(1), Operation::or(vec![hello(0), hello(2)])]);
+        assert_eq!(format!("{:?}", optimize(&tree)), format!("{:?}", expected));
+    }
+}
@@ -0,0 +1,158 @@
+use std::cmp;
+
+use slice_group_by::GroupBy;
+
+use crate::bucket_sort::{BareMatch, RawDocument};
+use crate::{Position, attribute_of};
+
+// The maximum distance between two consecutive query words,
+// farther words are considered as unrelated.
+pub const MAX_DISTANCE: usize = 8;
+
+// Returns the distance between two words, words in the reverse order are
+// considered farther and words in different attributes are unrelated.
+pub fn distance(left: Position, right: Position) -> usize {
+    if attribute_of(left) != attribute_of(right) { return MAX_DISTANCE }
+    let distance = if left < right { right - left } else { left - right + 1 };
+    cmp::min(distance as usize, MAX_DISTANCE)
+}
+
+// Returns the smallest distance between any of the left positions
+// and any of the right positions, both must be sorted.
+fn min_distance(lefts: &[Position], rights: &[Position]) -> usize {
+    let mut best = MAX_DISTANCE;
+
+    for &right in rights {
+        // The closest left positions are on each side of the right one.
+        let i = match lefts.binary_search(&right) {
+            Ok(i) | Err(i) => i,
+        };
+
+        if let Some(&left) = i.checked_sub(1).and_then(|i| lefts.get(i)) {
+            best = cmp::min(best, distance(left, right));
+        }
+        if let Some(&left) = lefts.get(i) {
+            best = cmp::min(best, distance(left, right));
+        }
+        if best == 1 { break }
+    }
+
+    best
+}
+
+// Returns the sum of the smallest distances between the consecutive query
+// words matched by the document. The consecutive words are found using the
+// mapped ranges, a synonym ending where another query starts is followed by it.
+// The matches must be sorted by query index, query length and position.
+pub fn document_proximity(matches: &[BareMatch]) -> usize {
+    let groups: Vec<_> = matches.linear_group_by(|a, b| {
+        (a.query_index, a.query_len) == (b.query_index, b.query_len)
+    })
+    .map(|group| {
+        let m = group[0];
+        let mut positions: Vec<_> = group.iter().map(|m| m.position).collect();
+        positions.dedup();
+        (m.query_index, m.query_index + m.query_len, positions)
+    })
+    .collect();
+
+    let mut boundaries: Vec<_> = groups.iter().map(|(_, end, _)| *end).collect();
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut proximity = 0;
+    for boundary in boundaries {
+        let lefts = groups.iter().filter(|(_, end, _)| *end == boundary);
+        let rights: Vec<_> = groups.iter().filter(|(start, _, _)| *start == boundary).collect();
+
+        // There is no match for the word following this boundary.
+        if rights.is_empty() { continue }
+
+        let best = lefts.flat_map(|(_, _, lefts)| {
+            rights.iter().map(move |(_, _, rights)| min_distance(lefts, rights))
+        })
+        .min();
+
+        proximity += best.unwrap_or(MAX_DISTANCE);
+    }
+
+    proximity
+}
+
+// Keeps the documents where the consecutive query words are
+// in average at most `max_distance` words away from each other.
+pub fn retain_close_documents(documents: &mut Vec<RawDocument>, max_distance: usize) {
+    documents.retain(|document| {
+        let groups = document.matches.linear_group_by(|a, b| a.query_index == b.query_index).count();
+        let pairs = groups.saturating_sub(1);
+        document_proximity(&document.matches) <= pairs * max_distance
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode_position;
+
+    fn bare(query_index: usize, query_len: usize, position: Position) -> BareMatch {
+        let (original_index, original_len) = (query_index, query_len);
+        BareMatch { query_index, query_len, position, typos: 0, is_exact: true, original_index, original_len }
+    }
+
+    fn sorted(mut matches: Vec<BareMatch>) -> Vec<BareMatch> {
+        matches.sort_unstable();
+        matches
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(distance(2, 3), 1);
+        assert_eq!(distance(3, 2), 2);
+        assert_eq!(distance(2, 2), 1);
+        assert_eq!(distance(0, 100), MAX_DISTANCE);
+        assert_eq!(distance(encode_position(0, 3), encode_position(1, 4)), MAX_DISTANCE);
+    }
+
+    #[test]
+    fn consecutive_words() {
+        // hello world
+        let matches = sorted(vec![bare(0, 1, 10), bare(0, 1, 3), bare(1, 1, 4), bare(1, 1, 20)]);
+        assert_eq!(document_proximity(&matches), 1);
+
+        // world hello
+        let matches = sorted(vec![bare(0, 1, 5), bare(1, 1, 4)]);
+        assert_eq!(document_proximity(&matches), 2);
+    }
+
+    #[test]
+    fn mapped_synonym_ranges() {
+        // nyc subway, where nyc = new york city
+        //  new       york      city      subway
+        // (0, 1)    (1, 1)    (2, 1)     (3, 1)
+        // \_____________________/
+        //         nyc (0, 3)
+        let matches = sorted(vec![
+            bare(0, 3, 7),  // nyc
+            bare(3, 1, 8),  // subway
+            bare(0, 1, 0),  // new
+            bare(1, 1, 1),  // york
+        ]);
+
+        // new york are next to each other, nyc is next to subway
+        // but there is no city to link york to subway.
+        assert_eq!(document_proximity(&matches), 2);
+    }
+
+    #[test]
+    fn retain_close() {
+        let far = RawDocument { id: 0, matches: sorted(vec![bare(0, 1, 0), bare(1, 1, 50)]) };
+        let close = RawDocument { id: 1, matches: sorted(vec![bare(0, 1, 0), bare(1, 1, 2)]) };
+        let alone = RawDocument { id: 2, matches: sorted(vec![bare(0, 1, 0)]) };
+
+        let mut documents = vec![far, close, alone];
+        retain_close_documents(&mut documents, 3);
+
+        let ids: Vec<_> = documents.iter().map(|d| d.id).collect();
+        assert_eq!(ids, vec![1, 2]);
+    }
+}
@@ -0,0 +1,221 @@
+use slice_group_by::StrGroupBy;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryToken {
+    Free(String),
+    Quoted(Vec<String>),
+    // A token that must be found in a field: `title:word` or `title:"a phrase"`.
+    Fielded(String, Box<QueryToken>),
+}
+
+impl QueryToken {
+    pub fn free(&self) -> Option<&str> {
+        match self {
+            QueryToken::Free(word) => Some(word),
+            QueryToken::Quoted(_) | QueryToken::Fielded(..) => None,
+        }
+    }
+
+    pub fn words(&self) -> &[String] {
+        match self {
+            QueryToken::Free(word) => std::slice::from_ref(word),
+            QueryToken::Quoted(words) => words,
+            QueryToken::Fielded(_, token) => token.words(),
+        }
+    }
+}
+
+fn is_field(name: &str, fields: &[String]) -> bool {
+    !name.is_empty() && fields.iter().any(|f| f == name)
+}
+
+// A word like `title:word` is restricted to the field, the word must not be empty.
+// The words like `12:30` or `re:subject`, where the prefix is not a field, are free words.
+fn free_token(word: &str, fields: &[String]) -> QueryToken {
+    match word.find(':') {
+        Some(i) if i + 1 != word.len() && is_field(&word[..i], fields) => {
+            let token = QueryToken::Free(word[i + 1..].to_owned());
+            QueryToken::Fielded(word[..i].to_owned(), Box::new(token))
+        },
+        _ => QueryToken::Free(word.to_owned()),
+    }
+}
+
+// A word starting with a single dash excludes the rest of the word,
+// the dashes alone or doubled are literal text, like `-`, `--` or `--word`.
+fn excluded_word(word: &str) -> Option<&str> {
+    match word.strip_prefix('-') {
+        Some(rest) if !rest.is_empty() && !rest.starts_with('-') => Some(rest),
+        _ => None,
+    }
+}
+
+pub fn split_words(text: &str) -> impl Iterator<Item=String> + '_ {
+    let words = text.linear_group_by_key(char::is_whitespace).map(ToOwned::to_owned);
+    words.filter(|s| !s.contains(char::is_whitespace))
+}
+
+// The queries and the documents are normalized the same way
+// for the words of a query to be found in the documents.
+pub fn normalize(text: &str) -> String {
+    text.to_lowercase()
+}
+
+// Splits the text of a document into words with the rules of the queries,
+// the quotes are separators as a query word can't contain them.
+pub fn tokenize(text: &str) -> Vec<String> {
+    normalize(text).split('"').flat_map(split_words).collect()
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ParsedQuery {
+    pub tokens: Vec<QueryToken>,
+    // The tokens prefixed by a dash, the documents
+    // containing them must be removed from the results.
+    pub excluded: Vec<QueryToken>,
+}
+
+// Every odd part between double quotes is a phrase, an unterminated
+// quote makes the rest of the query a phrase. Empty phrases are ignored.
+// The fields are the normalized names of the fields the words can be restricted to.
+pub fn parse_query(query: &str, fields: &[String]) -> ParsedQuery {
+    let query = normalize(query);
+    let mut parsed = ParsedQuery::default();
+    let mut exclude_next_phrase = false;
+    let mut next_phrase_field = None;
+
+    let parts: Vec<_> = query.split('"').collect();
+    for (i, part) in parts.iter().enumerate() {
+        if i % 2 == 0 {
+            let before_quote = i + 1 != parts.len() && !part.ends_with(char::is_whitespace);
+            let mut words = split_words(part).peekable();
+            while let Some(word) = words.next() {
+                // A dash and a field name glued to the next quote apply to the phrase: -title:"a b"
+                if before_quote && words.peek().is_none() {
+                    let (exclude, rest) = match word.strip_prefix('-') {
+                        Some(rest) if !rest.starts_with('-') => (true, rest),
+                        _ => (false, &word[..]),
+                    };
+                    let field = rest.strip_suffix(':').filter(|name| is_field(name, fields));
+                    if rest.is_empty() || field.is_some() {
+                        exclude_next_phrase = exclude;
+                        next_phrase_field = field.map(ToOwned::to_owned);
+                        continue;
+                    }
+                }
+
+                match excluded_word(&word) {
+                    Some(word) => parsed.excluded.push(free_token(word, fields)),
+                    None => parsed.tokens.push(free_token(&word, fields)),
+                }
+            }
+        } else {
+            let words: Vec<_> = split_words(part).collect();
+            let exclude = std::mem::replace(&mut exclude_next_phrase, false);
+            let field = next_phrase_field.take();
+            if !words.is_empty() {
+                let token = QueryToken::Quoted(words);
+                let token = match field {
+                    Some(field) => QueryToken::Fielded(field, Box::new(token)),
+                    None => token,
+                };
+                if exclude { parsed.excluded.push(token) } else { parsed.tokens.push(token) }
+            }
+        }
+    }
+
+    parsed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use big_s::S;
+
+    #[test]
+    fn free_words() {
+        let ParsedQuery { tokens, .. } = parse_query("  Hello   WORLD 2020 ", &[]);
+        assert_eq!(tokens, vec![
+            QueryToken::Free(S("hello")),
+            QueryToken::Free(S("world")),
+            QueryToken::Free(S("2020")),
+        ]);
+    }
+
+    #[test]
+    fn quoted_phrase() {
+        let ParsedQuery { tokens, .. } = parse_query(r#"the "New York city" subway"#, &[]);
+        assert_eq!(tokens, vec![
+            QueryToken::Free(S("the")),
+            QueryToken::Quoted(vec![S("new"), S("york"), S("city")]),
+            QueryToken::Free(S("subway")),
+        ]);
+    }
+
+    #[test]
+    fn glued_quotes() {
+        let ParsedQuery { tokens, .. } = parse_query(r#"hello"world wide"web"#, &[]);
+        assert_eq!(tokens, vec![
+            QueryToken::Free(S("hello")),
+            QueryToken::Quoted(vec![S("world"), S("wide")]),
+            QueryToken::Free(S("web")),
+        ]);
+    }
+
+    #[test]
+    fn unterminated_and_empty_quotes() {
+        let ParsedQuery { tokens, .. } = parse_query(r#"hello "" "world wide"#, &[]);
+        assert_eq!(tokens, vec![
+            QueryToken::Free(S("hello")),
+            QueryToken::Quoted(vec![S("world"), S("wide")]),
+        ]);
+    }
+
+    #[test]
+    fn excluded_words_and_phrases() {
+        let ParsedQuery { tokens, excluded } = parse_query(r#"new -york -"big apple" city - -- --foo --"bar""#, &[]);
+        assert_eq!(tokens, vec![
+            QueryToken::Free(S("new")),
+            QueryToken::Free(S("city")),
+            QueryToken::Free(S("-")),
+            QueryToken::Free(S("--")),
+            QueryToken::Free(S("--foo")),
+            QueryToken::Free(S("--")),
+            QueryToken::Quoted(vec![S("bar")]),
+        ]);
+        assert_eq!(excluded, vec![
+            QueryToken::Free(S("york")),
+            QueryToken::Quoted(vec![S("big"), S("apple")]),
+        ]);
+    }
+
+    #[test]
+    fn fielded_words_and_phrases() {
+        let fielded = |field: &str, token| QueryToken::Fielded(S(field), Box::new(token));
+
+        let fields = [S("title"), S("body"), S("tags")];
+
+        let query = r#"Title:Hello title:"New York" -tags:"big apple" -body:world title: :b"#;
+        let ParsedQuery { tokens, excluded } = parse_query(query, &fields);
+        assert_eq!(tokens, vec![
+            fielded("title", QueryToken::Free(S("hello"))),
+            fielded("title", QueryToken::Quoted(vec![S("new"), S("york")])),
+            QueryToken::Free(S("title:")),
+            QueryToken::Free(S(":b")),
+        ]);
+        assert_eq!(excluded, vec![
+            fielded("tags", QueryToken::Quoted(vec![S("big"), S("apple")])),
+            fielded("body", QueryToken::Free(S("world"))),
+        ]);
+
+        // The words with a colon are free words when their prefix is not a field.
+        let ParsedQuery { tokens, excluded } = parse_query(r#"12:30 re:subject -c++:b url:"a b""#, &fields);
+        assert_eq!(tokens, vec![
+            QueryToken::Free(S("12:30")),
+            QueryToken::Free(S("re:subject")),
+            QueryToken::Free(S("url:")),
+            QueryToken::Quoted(vec![S("a"), S("b")]),
+        ]);
+        assert_eq!(excluded, vec![QueryToken::Free(S("c++:b"))]);
+    }
+}
@@ -1,3 +1,4 @@
+use std::cmp;
 use std::collections::HashMap;
 use std::iter::FromIterator;
 use std::ops::Range;
@@ -46,13 +47,18 @@ impl QueryWordsMapper {
         let common_left = longest_common_prefix(left, &replacement);
         let common_right = longest_common_prefix(&replacement, right);
 
+        // The common words on both sides can't overlap, like with
+        // `world` replaced by `hello` in the query `hello world hello`.
+        let common_right = cmp::min(common_right, replacement.len() - common_left);
+
         for i in 0..common_left {
             let range = range.start - common_left + i..range.start - common_left + i + 1;
             let replacement = vec![replacement[i].clone()];
             self.mappings.insert(id + i, (range, replacement));
         }
 
-        {
+        // The replacement can be entirely made of common words.
+        if common_left + common_right < replacement.len() {
             let replacement = replacement[common_left..replacement.len() - common_right].iter().cloned().collect();
             self.mappings.insert(id + common_left, (range.clone(), replacement));
         }
@@ -412,4 +418,23 @@ mod tests {
         assert_eq!(mapping[&10], 1..5); // NY
         assert_eq!(mapping[&11], 2..7); // metro
     }
+
+    #[test]
+    fn overlapping_common_words() {
+        let query = ["hello", "world", "hello"];
+        //             0        1        2
+        let mut builder = QueryWordsMapper::new(&query);
+
+        // world = hello
+        builder.declare(1..2, 3, &["hello"]);
+        //                        3
+
+        let mapping = builder.mapping();
+
+        assert_eq!(mapping[&0], 0..1); // hello
+        assert_eq!(mapping[&1], 1..2); // world
+        assert_eq!(mapping[&2], 2..3); // hello
+
+        assert_eq!(mapping[&3], 0..1); // hello
+    }
 }
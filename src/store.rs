@@ -0,0 +1,388 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::{cmp, fmt, mem, slice, str};
+
+use fst::Streamer;
+use memmap::Mmap;
+use sdset::Set;
+
+use crate::error::Error;
+use crate::synonyms::Synonyms;
+use crate::{Context, DocId, DocIndex, PostingsList, PostingsRef};
+
+// The layout of an index file, all the integers are little endian:
+//
+// header      magic, version, then the offset and length of the sections
+// words       the fst of the words, the values are the indexes of the postings lists
+// synonyms    the synonyms serialized in JSON
+// attributes  the names of the attributes serialized in JSON
+// table       the offset and length of the docids and matches of every postings list
+// postings    the docids and matches, aligned to be borrowed from the memory mapped file
+pub const MAGIC: &[u8; 8] = b"OQTINDEX";
+pub const VERSION: u32 = 4;
+
+const HEADER_LEN: usize = 80;
+const ENTRY_LEN: usize = 32;
+const ALIGN: usize = 8;
+
+// The bytes written to align the sections.
+static ZEROS: [u8; ALIGN] = [0; ALIGN];
+
+// The bytes of the fst, owned or borrowed from a memory mapped file.
+#[derive(Clone)]
+pub(crate) enum Bytes {
+    Owned(Vec<u8>),
+    Mapped(Arc<Mmap>, Range<usize>),
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Bytes::Owned(bytes) => bytes,
+            Bytes::Mapped(mmap, range) => &mmap[range.clone()],
+        }
+    }
+}
+
+impl fmt::Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Bytes::Owned(bytes) => write!(f, "Owned({} bytes)", bytes.len()),
+            Bytes::Mapped(_, range) => write!(f, "Mapped({:?})", range),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Store {
+    Memory(Vec<PostingsList>),
+    // The postings lists are borrowed from the file using the table.
+    Mapped(Arc<Mmap>, Range<usize>),
+}
+
+impl Store {
+    pub fn get(&self, index: u64) -> Option<PostingsRef<'_>> {
+        match self {
+            Store::Memory(lists) => lists.get(index as usize).map(PostingsRef::from),
+            Store::Mapped(mmap, table) => {
+                let start = table.start.checked_add((index as usize).checked_mul(ENTRY_LEN)?)?;
+                if start + ENTRY_LEN > table.end { return None }
+                postings_ref(mmap, &mmap[start..start + ENTRY_LEN])
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Store::Memory(lists) => lists.len(),
+            Store::Mapped(_, table) => table.len() / ENTRY_LEN,
+        }
+    }
+}
+
+/// The types that are valid whatever their bytes are, as long
+/// as the bytes are correctly sized and aligned for them.
+///
+/// # Safety
+///
+/// Every sequence of bytes of the size of the type must be a valid value,
+/// the type can only be made of integers and must not contain padding.
+unsafe trait FromBytes: Copy {}
+
+unsafe impl FromBytes for DocId {}
+
+// A DocIndex only contains integers and is `repr(C)`.
+unsafe impl FromBytes for DocIndex {}
+
+fn cast_slice<T: FromBytes>(bytes: &[u8]) -> Option<&[T]> {
+    let size = mem::size_of::<T>();
+    if bytes.len() % size != 0 || bytes.as_ptr() as usize % mem::align_of::<T>() != 0 {
+        return None;
+    }
+
+    // Safety: the length and the alignment have been checked and
+    // T is valid for any bytes, the slice lives as long as the bytes.
+    Some(unsafe { slice::from_raw_parts(bytes.as_ptr() as *const T, bytes.len() / size) })
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_le_bytes(buf)
+}
+
+fn section(bytes: &[u8], offset: u64, len: u64, size: usize) -> Option<Range<usize>> {
+    let start = offset as usize;
+    let end = start.checked_add((len as usize).checked_mul(size)?)?;
+    if end <= bytes.len() { Some(start..end) } else { None }
+}
+
+fn postings_ref<'a>(bytes: &'a [u8], entry: &[u8]) -> Option<PostingsRef<'a>> {
+    let docids = section(bytes, read_u64(entry, 0), read_u64(entry, 8), mem::size_of::<DocId>())?;
+    let matches = section(bytes, read_u64(entry, 16), read_u64(entry, 24), mem::size_of::<DocIndex>())?;
+
+    // The sets are sorted when written, we don't check them again.
+    let docids = Set::new_unchecked(cast_slice(&bytes[docids])?);
+    let matches = Set::new_unchecked(cast_slice(&bytes[matches])?);
+
+    Some(PostingsRef { docids, matches, bitmap: None })
+}
+
+fn align(offset: usize) -> usize {
+    (offset + ALIGN - 1) / ALIGN * ALIGN
+}
+
+// Keeps track of the position in the file to align the sections.
+struct Writer<W> {
+    inner: W,
+    position: usize,
+}
+
+impl<W: Write> Writer<W> {
+    fn write(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.inner.write_all(bytes)?;
+        self.position += bytes.len();
+        Ok(())
+    }
+
+    fn pad_to(&mut self, offset: usize) -> Result<(), Error> {
+        while self.position < offset {
+            let len = cmp::min(offset - self.position, ZEROS.len());
+            self.write(&ZEROS[..len])?;
+        }
+        Ok(())
+    }
+}
+
+// Writes the context in the index format, it can be opened with `open_context`.
+pub fn write_context<W: Write>(ctx: &Context, writer: W) -> Result<(), Error> {
+    let words = ctx.words.as_fst().as_bytes();
+
+    let mut synonyms: Vec<_> = ctx.synonyms.iter().collect();
+    synonyms.sort_unstable();
+    let synonyms = serde_json::to_vec(&synonyms)?;
+    let attributes = serde_json::to_vec(&ctx.attributes)?;
+
+    let lists: Option<Vec<_>> = (0..ctx.store.len() as u64).map(|i| ctx.store.get(i)).collect();
+    let lists = lists.expect("the postings lists are all in the store");
+
+    let words_offset = HEADER_LEN;
+    let synonyms_offset = align(words_offset + words.len());
+    let attributes_offset = align(synonyms_offset + synonyms.len());
+    let table_offset = align(attributes_offset + attributes.len());
+
+    let mut offset = table_offset + lists.len() * ENTRY_LEN;
+    let mut table = Vec::with_capacity(lists.len() * ENTRY_LEN);
+    for PostingsRef { docids, matches, .. } in &lists {
+        let docids_offset = align(offset);
+        let matches_offset = align(docids_offset + docids.len() * mem::size_of::<DocId>());
+        offset = matches_offset + matches.len() * mem::size_of::<DocIndex>();
+
+        let entry = [docids_offset, docids.len(), matches_offset, matches.len()];
+        table.extend(entry.iter().flat_map(|n| (*n as u64).to_le_bytes().to_vec()));
+    }
+
+    let mut writer = Writer { inner: BufWriter::new(writer), position: 0 };
+
+    writer.write(MAGIC)?;
+    writer.write(&VERSION.to_le_bytes())?;
+    writer.write(&0u32.to_le_bytes())?;
+    let sections = [
+        words_offset, words.len(),
+        synonyms_offset, synonyms.len(),
+        attributes_offset, attributes.len(),
+        table_offset, lists.len(),
+    ];
+    for n in &sections {
+        writer.write(&(*n as u64).to_le_bytes())?;
+    }
+
+    writer.pad_to(words_offset)?;
+    writer.write(words)?;
+    writer.pad_to(synonyms_offset)?;
+    writer.write(&synonyms)?;
+    writer.pad_to(attributes_offset)?;
+    writer.write(&attributes)?;
+    writer.pad_to(table_offset)?;
+    writer.write(&table)?;
+
+    // The fields of a DocIndex are written in order, the padding
+    // at the end of the struct is filled with zeros.
+    let padding = mem::size_of::<DocIndex>() - mem::size_of::<DocId>() - mem::size_of::<crate::Position>();
+    for PostingsRef { docids, matches, .. } in &lists {
+        writer.pad_to(align(writer.position))?;
+        for docid in docids.iter() {
+            writer.write(&docid.to_le_bytes())?;
+        }
+
+        writer.pad_to(align(writer.position))?;
+        for DocIndex { docid, position } in matches.iter() {
+            writer.write(&docid.to_le_bytes())?;
+            writer.write(&position.to_le_bytes())?;
+            writer.write(&ZEROS[..padding])?;
+        }
+    }
+
+    writer.inner.flush()?;
+    Ok(())
+}
+
+pub fn save_context<P: AsRef<Path>>(ctx: &Context, path: P) -> Result<(), Error> {
+    write_context(ctx, File::create(path)?)
+}
+
+fn invalid(message: &str) -> Error {
+    Error::InvalidIndex(message.to_owned())
+}
+
+// Memory maps an index file, the postings lists are borrowed from the file.
+pub fn open_context<P: AsRef<Path>>(path: P) -> Result<Context, Error> {
+    if cfg!(target_endian = "big") {
+        return Err(invalid("the index can only be opened on little endian targets"));
+    }
+
+    let file = File::open(path)?;
+    // Safety: the file must not be modified while it is mapped.
+    let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+    let bytes: &[u8] = &mmap;
+
+    if bytes.len() < HEADER_LEN || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(invalid("not an index file"));
+    }
+
+    let mut version = [0; 4];
+    version.copy_from_slice(&bytes[8..12]);
+    let version = u32::from_le_bytes(version);
+    if version != VERSION {
+        let message = format!("unsupported version {}, expected {}", version, VERSION);
+        return Err(Error::InvalidIndex(message));
+    }
+
+    let section_at = |i: usize, size: usize| {
+        let offset = 16 + i * 16;
+        section(bytes, read_u64(bytes, offset), read_u64(bytes, offset + 8), size)
+            .ok_or_else(|| invalid("a section is out of the file"))
+    };
+
+    let words = section_at(0, 1)?;
+    let synonyms = section_at(1, 1)?;
+    let attributes = section_at(2, 1)?;
+    let table = section_at(3, ENTRY_LEN)?;
+
+    let words = fst::Map::new(Bytes::Mapped(mmap.clone(), words))
+        .map_err(|e| Error::InvalidIndex(e.to_string()))?;
+
+    // The words are returned as strings by the searches, they must be valid UTF-8.
+    let mut stream = words.stream();
+    while let Some((word, _)) = stream.next() {
+        if str::from_utf8(word).is_err() {
+            return Err(invalid("a word is not valid UTF-8"));
+        }
+    }
+
+    let synonyms: Vec<(Vec<String>, Vec<Vec<String>>)> = serde_json::from_slice(&bytes[synonyms])?;
+    let synonyms: Synonyms = synonyms.into_iter().collect();
+    let attributes: Vec<String> = serde_json::from_slice(&bytes[attributes])?;
+
+    let store = Store::Mapped(mmap.clone(), table);
+    if words.len() != store.len() {
+        return Err(invalid("the number of words and postings lists differ"));
+    }
+    if (0..store.len() as u64).any(|i| store.get(i).is_none()) {
+        return Err(invalid("a postings list is out of the file or misaligned"));
+    }
+
+    let mut context = Context::from_parts(synonyms, words, store);
+    context.attributes = attributes;
+
+    Ok(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use big_s::S;
+    use sdset::SetBuf;
+    use crate::fixtures::search;
+
+    fn postings_list(matches: &[(DocId, crate::Position)]) -> PostingsList {
+        let matches: Vec<_> = matches.iter().map(|&(docid, position)| DocIndex { docid, position }).collect();
+        let mut docids: Vec<_> = matches.iter().map(|m| m.docid).collect();
+        docids.dedup();
+        PostingsList { docids: SetBuf::new(docids).unwrap(), matches: SetBuf::new(matches).unwrap() }
+    }
+
+    fn context() -> Context {
+        let mut synonyms = HashMap::new();
+        synonyms.insert(vec![S("earth")], vec![vec![S("world")]]);
+
+        let mut postings = HashMap::new();
+        postings.insert(S("hello"), postings_list(&[(1, 0), (1, 5), (3, 0), (300, 2)]));
+        postings.insert(S("world"), postings_list(&[(1, 1), (2, 0), (300, 1)]));
+        postings.insert(S("worlds"), postings_list(&[(4, 7)]));
+
+        let mut context = Context::new(synonyms, postings);
+        context.attributes = vec![S("title"), S("body")];
+        context
+    }
+
+    #[test]
+    fn write_and_open() {
+        let ctx = context();
+        let path = std::env::temp_dir().join(format!("oqt-store-{}.idx", std::process::id()));
+        save_context(&ctx, &path).unwrap();
+
+        let opened = open_context(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for word in &["hello", "world", "worlds"] {
+            let (expected, found) = (ctx.postings_list(word).unwrap(), opened.postings_list(word).unwrap());
+            assert_eq!(expected.docids, found.docids);
+            assert_eq!(expected.matches, found.matches);
+        }
+
+        assert!(opened.postings_list("hell").is_none());
+        assert_eq!(opened.synonyms, ctx.synonyms);
+        assert_eq!(opened.attributes, ctx.attributes);
+
+        for query in &["hello world", "earth", "worl", "\"hello world\""] {
+            assert_eq!(search(&opened, query), search(&ctx, query));
+        }
+    }
+
+    #[test]
+    fn invalid_files() {
+        let mut bytes = Vec::new();
+        write_context(&context(), &mut bytes).unwrap();
+
+        let path = std::env::temp_dir().join(format!("oqt-invalid-{}.idx", std::process::id()));
+
+        let mut other_version = bytes.clone();
+        other_version[8] += 1;
+        std::fs::write(&path, &other_version).unwrap();
+        assert!(open_context(&path).is_err());
+
+        std::fs::write(&path, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(open_context(&path).is_err());
+
+        std::fs::write(&path, b"hello world").unwrap();
+        assert!(open_context(&path).is_err());
+
+        // The words of the fst are not checked when it is built from bytes.
+        let words = fst::Map::from_iter(vec![(&b"hello"[..], 0), (&b"w\xffrld"[..], 1)]).unwrap();
+        let words = fst::Map::new(Bytes::Owned(words.as_fst().as_bytes().to_vec())).unwrap();
+        let lists = vec![postings_list(&[(1, 0)]), postings_list(&[(1, 1)])];
+        let invalid_words = Context::from_parts(HashMap::new(), words, Store::Memory(lists));
+
+        let mut bytes = Vec::new();
+        write_context(&invalid_words, &mut bytes).unwrap();
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(open_context(&path), Err(Error::InvalidIndex(_))));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::error::Error;
+use crate::query_parser::{normalize, split_words};
+
+// The alternatives of a sequence of normalized words.
+pub type Synonyms = HashMap<Vec<String>, Vec<Vec<String>>>;
+
+// Normalizes the terms the same way the queries are,
+// the terms that don't contain any word are ignored.
+fn normalize_terms<'a, I>(terms: I) -> Vec<Vec<String>>
+where I: IntoIterator<Item=&'a str>,
+{
+    terms.into_iter()
+        .map(|term| split_words(&normalize(term)).collect::<Vec<_>>())
+        .filter(|words| !words.is_empty())
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct SynonymsBuilder {
+    synonyms: Synonyms,
+}
+
+impl SynonymsBuilder {
+    pub fn new() -> SynonymsBuilder {
+        SynonymsBuilder::default()
+    }
+
+    fn insert(&mut self, from: &[String], to: &[String]) {
+        if from == to { return }
+        let alternatives = self.synonyms.entry(from.to_vec()).or_default();
+        if !alternatives.iter().any(|a| a == to) {
+            alternatives.push(to.to_vec());
+        }
+    }
+
+    // Every term is a synonym of all the other ones, like `nyc`, `new york` and `new york city`.
+    pub fn add_equivalence<'a, I>(&mut self, terms: I)
+    where I: IntoIterator<Item=&'a str>,
+    {
+        let terms = normalize_terms(terms);
+        for from in &terms {
+            for to in &terms {
+                self.insert(from, to);
+            }
+        }
+    }
+
+    // The `from` terms are expanded to the `to` terms but not the other way around.
+    pub fn add_one_way<'a, I, J>(&mut self, from: I, to: J)
+    where I: IntoIterator<Item=&'a str>,
+          J: IntoIterator<Item=&'a str>,
+    {
+        let to = normalize_terms(to);
+        for from in normalize_terms(from) {
+            for to in &to {
+                self.insert(&from, to);
+            }
+        }
+    }
+
+    pub fn build(self) -> Synonyms {
+        self.synonyms
+    }
+}
+
+// Parses the Solr format, one rule per line: `a, b, c` are equivalent
+// terms and `a, b => c, d` expands `a` and `b` to `c` and `d` only.
+// The empty lines and the ones starting with `#` are ignored.
+pub fn parse_solr(text: &str) -> Result<Synonyms, Error> {
+    let mut builder = SynonymsBuilder::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') { continue }
+
+        let sides: Vec<_> = line.split("=>").collect();
+        match sides.as_slice() {
+            [terms] => builder.add_equivalence(terms.split(',')),
+            [from, to] if !from.trim().is_empty() && !to.trim().is_empty() => {
+                builder.add_one_way(from.split(','), to.split(','));
+            },
+            _ => return Err(Error::InvalidSynonyms(format!("invalid rule at line {}", i + 1))),
+        }
+    }
+
+    Ok(builder.build())
+}
+
+fn json_terms(value: &Value) -> Option<Vec<&str>> {
+    value.as_array()?.iter().map(Value::as_str).collect()
+}
+
+// Parses the JSON format, an array of equivalent terms
+// or an object of terms expanded to other terms:
+//
+// [["nyc", "new york", "new york city"]]
+// {"nyc": ["new york", "new york city"]}
+pub fn parse_json(text: &str) -> Result<Synonyms, Error> {
+    let mut builder = SynonymsBuilder::new();
+
+    match serde_json::from_str(text)? {
+        Value::Array(groups) => for group in &groups {
+            let terms = json_terms(group).ok_or_else(|| {
+                Error::InvalidSynonyms(format!("expected an array of strings, found {}", group))
+            })?;
+            builder.add_equivalence(terms);
+        },
+        Value::Object(rules) => for (from, to) in &rules {
+            let terms = json_terms(to).ok_or_else(|| {
+                Error::InvalidSynonyms(format!("expected an array of strings for {:?}, found {}", from, to))
+            })?;
+            builder.add_one_way(Some(from.as_str()), terms);
+        },
+        other => {
+            let message = format!("expected an array or an object, found {}", other);
+            return Err(Error::InvalidSynonyms(message));
+        },
+    }
+
+    Ok(builder.build())
+}
+
+// Reads a synonyms file, the JSON format is used for the .json files.
+pub fn read_synonyms<P: AsRef<Path>>(path: P) -> Result<Synonyms, Error> {
+    let text = fs::read_to_string(&path)?;
+    match path.as_ref().extension().and_then(|e| e.to_str()) {
+        Some("json") => parse_json(&text),
+        _ => parse_solr(&text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use big_s::S;
+
+    fn words(text: &str) -> Vec<String> {
+        text.split_whitespace().map(ToOwned::to_owned).collect()
+    }
+
+    #[test]
+    fn solr_rules() {
+        let synonyms = parse_solr("
+            # new york city
+            NYC, New  York, new york city
+            hello, good morning => hi
+            world => earth, nature
+        ").unwrap();
+
+        assert_eq!(synonyms[&words("nyc")], vec![words("new york"), words("new york city")]);
+        assert_eq!(synonyms[&words("new york")], vec![words("nyc"), words("new york city")]);
+        assert_eq!(synonyms[&words("new york city")], vec![words("nyc"), words("new york")]);
+
+        assert_eq!(synonyms[&words("hello")], vec![words("hi")]);
+        assert_eq!(synonyms[&words("good morning")], vec![words("hi")]);
+        assert_eq!(synonyms[&words("world")], vec![words("earth"), words("nature")]);
+        assert!(!synonyms.contains_key(&words("hi")));
+        assert!(!synonyms.contains_key(&words("earth")));
+
+        assert!(parse_solr("a => b => c").is_err());
+        assert!(parse_solr(" => b").is_err());
+    }
+
+    #[test]
+    fn merged_rules() {
+        let synonyms = parse_solr("
+            hello => hi
+            hello, hi
+            hello, bonjour
+        ").unwrap();
+
+        assert_eq!(synonyms[&words("hello")], vec![words("hi"), words("bonjour")]);
+        assert_eq!(synonyms[&words("hi")], vec![words("hello")]);
+    }
+
+    #[test]
+    fn json_rules() {
+        let synonyms = parse_json(r#"[["nyc", "New York"]]"#).unwrap();
+        assert_eq!(synonyms[&words("nyc")], vec![words("new york")]);
+        assert_eq!(synonyms[&words("new york")], vec![words("nyc")]);
+
+        let synonyms = parse_json(r#"{"world": ["earth", "nature"]}"#).unwrap();
+        assert_eq!(synonyms[&vec![S("world")]], vec![words("earth"), words("nature")]);
+        assert_eq!(synonyms.len(), 1);
+
+        assert!(parse_json(r#"{"world": "earth"}"#).is_err());
+        assert!(parse_json(r#""earth""#).is_err());
+    }
+
+    #[test]
+    fn replaced_by_a_query_word() {
+        use crate::fixtures::{search, titles};
+
+        let mut ctx = titles(&["hello world hello", "hello hello"]);
+        ctx.synonyms = parse_solr("world => hello").unwrap();
+
+        assert_eq!(search(&ctx, "hello world hello"), vec![0, 1]);
+    }
+}
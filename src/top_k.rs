@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::bucket_sort::{RawDocument, bucket_sort, raw_documents};
+use crate::criterion::{Criteria, number_of_typos};
+use crate::explain::Tracer;
+use crate::levenshtein::MAX_TYPOS;
+use crate::{Context, Evaluation, Operation, QueryId, traverse};
+
+#[derive(Debug)]
+pub struct TopK {
+    pub documents: Vec<RawDocument>,
+    // The number of typos per word the tree has been evaluated with,
+    // the evaluation stops as soon as k documents have been settled.
+    pub max_typos: u8,
+    // The number of documents found by the last evaluated typo level.
+    pub candidates: usize,
+    // The number of operations evaluated over all the typo levels and the number of
+    // the ones that were not evaluated again as they were kept from a previous level.
+    pub evaluated: usize,
+    pub cache_hits: usize,
+}
+
+// Returns the k best documents, if the first criterion is the typo one the tree is evaluated
+// with more and more typos per word. The documents with at most n typos in total are all found
+// with n typos per word and are ranked before the other ones, the evaluation stops as soon as
+// there are k of them. The operations that don't depend on the typos are only evaluated once.
+pub fn top_k(
+    ctx: &Context,
+    tree: &Operation,
+    mapping: &HashMap<QueryId, Range<usize>>,
+    criteria: &Criteria,
+    k: usize,
+) -> TopK
+{
+    let typo_first = criteria.iter().next().map_or(false, |c| c.is_typo());
+    let mut max_typos = if typo_first { 0 } else { MAX_TYPOS };
+    let mut evaluation = Evaluation::new();
+    let mut tracer = Tracer::disabled();
+
+    loop {
+        let docids = traverse(ctx, tree, max_typos, &mut tracer, &mut evaluation);
+        let mut documents = raw_documents(&docids, evaluation.queries(), mapping);
+
+        // The documents with more typos in total can be outranked
+        // by documents only found with more typos per word.
+        if max_typos < MAX_TYPOS {
+            documents.retain(|d| number_of_typos(d) <= max_typos as usize);
+        }
+
+        if documents.len() >= k || max_typos == MAX_TYPOS {
+            let documents = bucket_sort(documents, criteria, 0..k);
+            return TopK {
+                documents,
+                max_typos,
+                candidates: docids.len(),
+                evaluated: tracer.evaluated(),
+                cache_hits: tracer.cache_hits(),
+            };
+        }
+
+        evaluation.forget_typos(max_typos);
+        max_typos += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::create_query_tree;
+    use crate::criterion::{DocumentId, Typo};
+    use crate::fixtures::{context, document};
+
+    #[test]
+    fn settled_by_total_typos() {
+        let ctx = context(&[
+            // Found with one typo per word but four typos in total.
+            document(0, &[("title", "strawberrx applez bananaz cherriez")]),
+            // Found with two typos per word but two typos in total.
+            document(1, &[("title", "strawbxxry apples bananas cherries")]),
+            document(2, &[("title", "apples")]),
+        ]);
+
+        let criteria = Criteria::new().push(Typo).push(DocumentId);
+        let top = |query: &str, k: usize| {
+            let (tree, mapping) = create_query_tree(&ctx, query);
+            let TopK { documents, max_typos, .. } = top_k(&ctx, &tree, &mapping, &criteria, k);
+            (documents.iter().map(|d| d.id).collect::<Vec<_>>(), max_typos)
+        };
+
+        assert_eq!(top("strawberry apples bananas cherries", 1), (vec![1], 2));
+        assert_eq!(top("strawberry apples bananas cherries", 2), (vec![1, 0], 2));
+        assert_eq!(top("apples", 1), (vec![1], 0));
+
+        let criteria = Criteria::new().push(DocumentId);
+        let (tree, mapping) = create_query_tree(&ctx, "apples");
+        let TopK { documents, max_typos, candidates, evaluated, cache_hits } = top_k(&ctx, &tree, &mapping, &criteria, 1);
+        assert_eq!((documents[0].id, max_typos, candidates), (0, MAX_TYPOS, 3));
+        assert_eq!((evaluated, cache_hits), (1, 0));
+
+        // The tree has five operations evaluated at three typo levels, `apples` is not evaluated at the
+        // first one as `strawberry` isn't found without typos. The concatenation is kept after the
+        // first level and `apples`, that can only have one typo, after the second.
+        let criteria = Criteria::new().push(Typo).push(DocumentId);
+        let (tree, mapping) = create_query_tree(&ctx, "strawberry apples");
+        let TopK { max_typos, evaluated, cache_hits, .. } = top_k(&ctx, &tree, &mapping, &criteria, 3);
+        assert_eq!((max_typos, evaluated, cache_hits), (MAX_TYPOS, 11, 3));
+    }
+}
@@ -0,0 +1,140 @@
+use std::cmp::Reverse;
+use std::fmt;
+
+use crate::Context;
+
+// Scores a split from the number of documents containing each of its words,
+// the splits with the highest scores are the best ones.
+pub trait SplitScore {
+    fn score(&self, frequencies: &[usize]) -> usize;
+}
+
+impl<F: Fn(&[usize]) -> usize> SplitScore for F {
+    fn score(&self, frequencies: &[usize]) -> usize {
+        (self)(frequencies)
+    }
+}
+
+// Prefers the splits where the rarest word is the most frequent.
+pub struct MinFrequency;
+
+impl SplitScore for MinFrequency {
+    fn score(&self, frequencies: &[usize]) -> usize {
+        frequencies.iter().copied().min().unwrap_or(0)
+    }
+}
+
+// Splits a word into words of the dictionary, like `helloworld` into `hello world`.
+pub struct WordSplitter {
+    // The maximum number of splits returned, the best ones first.
+    pub max_splits: usize,
+    // The words with at least this number of characters can also be split into three words.
+    pub three_words_len: Option<usize>,
+    // The number of documents every word of a split must be found in.
+    pub min_frequency: usize,
+    pub score: Box<dyn SplitScore>,
+}
+
+impl Default for WordSplitter {
+    fn default() -> WordSplitter {
+        WordSplitter {
+            max_splits: 1,
+            three_words_len: None,
+            min_frequency: 1,
+            score: Box::new(MinFrequency),
+        }
+    }
+}
+
+impl fmt::Debug for WordSplitter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WordSplitter")
+            .field("max_splits", &self.max_splits)
+            .field("three_words_len", &self.three_words_len)
+            .field("min_frequency", &self.min_frequency)
+            .finish()
+    }
+}
+
+impl WordSplitter {
+    // Returns the best splits of the word, the splits with equal
+    // scores are kept in the order of their first split position.
+    pub fn split<'a>(&self, ctx: &Context, word: &'a str) -> Vec<Vec<&'a str>> {
+        let frequency = |word: &str| ctx.postings_list(word).map_or(0, |pl| pl.docids.len());
+        let min_frequency = self.min_frequency.max(1);
+
+        let bounds: Vec<_> = word.char_indices().skip(1).map(|(i, _)| i).collect();
+        let three_words = self.three_words_len.map_or(false, |len| bounds.len() + 1 >= len);
+
+        let mut candidates = Vec::new();
+        for (n, &i) in bounds.iter().enumerate() {
+            let (left, right) = word.split_at(i);
+            candidates.push(vec![left, right]);
+
+            if three_words {
+                for &j in &bounds[n + 1..] {
+                    candidates.push(vec![left, &word[i..j], &word[j..]]);
+                }
+            }
+        }
+
+        let mut splits: Vec<_> = candidates.into_iter().filter_map(|words| {
+            let frequencies: Vec<_> = words.iter().map(|w| frequency(w)).collect();
+            if frequencies.iter().any(|&f| f < min_frequency) { return None }
+            Some((self.score.score(&frequencies), words))
+        })
+        .collect();
+
+        splits.sort_by_key(|(score, _)| Reverse(*score));
+        splits.into_iter().take(self.max_splits).map(|(_, words)| words).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use big_s::S;
+    use sdset::SetBuf;
+    use crate::PostingsList;
+
+    fn context(words: &[(&str, usize)]) -> Context {
+        let postings = words.iter().map(|&(word, len)| {
+            let docids = SetBuf::new_unchecked((0..len as u32).collect());
+            (S(word), PostingsList { docids, matches: SetBuf::default() })
+        });
+        Context::new(HashMap::new(), postings.collect())
+    }
+
+    #[test]
+    fn best_splits() {
+        let ctx = context(&[("hell", 20), ("o", 3), ("hello", 10), ("world", 5), ("wor", 4), ("ld", 8)]);
+
+        let splitter = WordSplitter::default();
+        assert_eq!(splitter.split(&ctx, "helloworld"), vec![vec!["hello", "world"]]);
+        assert_eq!(splitter.split(&ctx, "hellx"), Vec::<Vec<&str>>::new());
+
+        let splitter = WordSplitter { max_splits: 3, three_words_len: Some(10), ..WordSplitter::default() };
+        assert_eq!(splitter.split(&ctx, "helloworld"), vec![
+            vec!["hello", "world"],
+            vec!["hello", "wor", "ld"],
+            vec!["hell", "o", "world"],
+        ]);
+
+        let splitter = WordSplitter { max_splits: 3, min_frequency: 5, ..WordSplitter::default() };
+        assert_eq!(splitter.split(&ctx, "helloworld"), vec![vec!["hello", "world"]]);
+    }
+
+    #[test]
+    fn custom_score() {
+        let ctx = context(&[("a", 1), ("bcd", 1), ("ab", 10), ("cd", 2)]);
+
+        let splitter = WordSplitter { max_splits: 2, ..WordSplitter::default() };
+        assert_eq!(splitter.split(&ctx, "abcd"), vec![vec!["ab", "cd"], vec!["a", "bcd"]]);
+
+        // Prefers the rarest words.
+        let score = |frequencies: &[usize]| usize::max_value() - frequencies.iter().sum::<usize>();
+        let splitter = WordSplitter { max_splits: 2, score: Box::new(score), ..WordSplitter::default() };
+        assert_eq!(splitter.split(&ctx, "abcd"), vec![vec!["a", "bcd"], vec!["ab", "cd"]]);
+    }
+}